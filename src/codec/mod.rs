@@ -2,28 +2,45 @@ use lzf;
 use com::*;
 use consts::*;
 use self::super::{FromBuf, Shift};
+use std::borrow::Cow;
+
+mod stream;
+
+pub use self::stream::*;
 
 #[derive(Debug, Clone)]
 pub enum Length {
     Small(u8),
     Normal(u16),
-    Large(u32),
+    Large32(u32),
+    Large64(u64),
 }
 
 impl FromBuf for Length {
-    /// judge by prefix two bits.
+    /// judge by prefix two bits, `0b10` is followed by a big endian
+    /// u32 (0x80) or u64 (0x81).
     fn from_buf(src: &[u8]) -> Result<Self> {
+        more!(src.is_empty());
         let ltype = src[0] >> 6;
         match ltype {
             REDIS_RDB_6BITLEN => Ok(Length::Small(src[0] & 0x3f)),
             REDIS_RDB_14BITLEN => {
+                more!(src.len() < 2);
                 let value = buf_to_u16_big(src);
                 Ok(Length::Normal(value & 0x3fff))
             }
             REDIS_RDB_32BITLEN => {
-                more!(src.len() < 1 + 3);
-                let value = buf_to_u32_big(src);
-                Ok(Length::Large(value & 0x3fff_ffff))
+                match src[0] {
+                    REDIS_RDB_32BITLEN_FLAG => {
+                        more!(src.len() < 1 + 4);
+                        Ok(Length::Large32(buf_to_u32_big(&src[1..])))
+                    }
+                    REDIS_RDB_64BITLEN_FLAG => {
+                        more!(src.len() < 1 + 8);
+                        Ok(Length::Large64(buf_to_u64_big(&src[1..])))
+                    }
+                    _ => Err(Error::Faild("wrong length encode prefix")),
+                }
             }
             REDIS_RDB_ENCVAL => Err(Error::Other),
             _ => Err(Error::Faild("wrong length encode prefix")),
@@ -33,10 +50,16 @@ impl FromBuf for Length {
 
 impl Length {
     pub fn length(&self) -> usize {
-        match self {
-            &Length::Small(val) => val as usize,
-            &Length::Normal(val) => val as usize,
-            &Length::Large(val) => val as usize,
+        self.value() as usize
+    }
+
+    /// the full value, 64 bits lengths also carry stream ids and module ids.
+    pub fn value(&self) -> u64 {
+        match *self {
+            Length::Small(val) => val as u64,
+            Length::Normal(val) => val as u64,
+            Length::Large32(val) => val as u64,
+            Length::Large64(val) => val,
         }
     }
 }
@@ -44,10 +67,11 @@ impl Length {
 impl Shift for Length {
     #[inline]
     fn shift(&self) -> usize {
-        match self {
-            &Length::Small(_) => 1,
-            &Length::Normal(_) => 2,
-            &Length::Large(_) => 5,
+        match *self {
+            Length::Small(_) => 1,
+            Length::Normal(_) => 2,
+            Length::Large32(_) => 1 + 4,
+            Length::Large64(_) => 1 + 8,
         }
     }
 }
@@ -57,15 +81,15 @@ impl Shift for Length {
 pub enum RedisString {
     LengthPrefix { len: Length, data: Vec<u8> },
     StrInt(StrInt),
-    LZF(LZFString),
+    Lzf(LZFString),
 }
 
 impl Shift for RedisString {
     fn shift(&self) -> usize {
         match self {
-            &RedisString::LengthPrefix { ref len, ref data } => len.shift() + data.len(),
-            &RedisString::StrInt(ref ival) => ival.shift(),
-            &RedisString::LZF(ref lzf) => lzf.shift(),
+            RedisString::LengthPrefix { len, data } => len.shift() + data.len(),
+            RedisString::StrInt(ival) => ival.shift(),
+            RedisString::Lzf(lzf) => lzf.shift(),
         }
     }
 }
@@ -80,6 +104,15 @@ impl FromBuf for RedisString {
 }
 
 impl RedisString {
+    /// the bytes of the string, only integers are formatted into a new buffer.
+    pub fn as_bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            RedisString::LengthPrefix { data, .. } => Cow::Borrowed(data),
+            RedisString::StrInt(v) => Cow::Owned(format!("{}", v.value()).into_bytes()),
+            RedisString::Lzf(lzf) => Cow::Borrowed(&lzf.buf),
+        }
+    }
+
     pub fn into_data(self) -> Vec<u8> {
         match self {
             RedisString::LengthPrefix { data, .. } => data,
@@ -87,17 +120,18 @@ impl RedisString {
                 let strv = format!("{}", v.value());
                 strv.into_bytes()
             }
-            RedisString::LZF(LZFString { buf, .. }) => buf,
+            RedisString::Lzf(LZFString { buf, .. }) => buf,
         }
     }
 
     fn length_prefix(src: &[u8]) -> Result<RedisString> {
         let length = Length::from_buf(src)?;
+        more!(src.len() < length.shift() + length.length());
         let mut data: Vec<u8> = Vec::with_capacity(length.length());
         data.extend_from_slice(&src[length.shift()..(length.shift() + length.length())]);
         Ok(RedisString::LengthPrefix {
             len: length,
-            data: data,
+            data,
         })
     }
 
@@ -108,7 +142,7 @@ impl RedisString {
 
     fn lzf(src: &[u8]) -> Result<RedisString> {
         let lzf = LZFString::from_buf(src)?;
-        Ok(RedisString::LZF(lzf))
+        Ok(RedisString::Lzf(lzf))
     }
 }
 
@@ -131,9 +165,9 @@ impl FromBuf for LZFString {
         let src = &src[shifted..(shifted + compressed_len.length())];
         let buf = lzf::decompress(src, original_len.length())?;
         Ok(LZFString {
-            compressed_len: compressed_len,
-            original_len: original_len,
-            buf: buf,
+            compressed_len,
+            original_len,
+            buf,
         })
     }
 }
@@ -156,10 +190,10 @@ pub enum StrInt {
 impl Shift for StrInt {
     #[inline]
     fn shift(&self) -> usize {
-        match self {
-            &StrInt::Small(_) => 1 + 1,
-            &StrInt::Normal(_) => 1 + 2,
-            &StrInt::Large(_) => 1 + 4,
+        match *self {
+            StrInt::Small(_) => 1 + 1,
+            StrInt::Normal(_) => 1 + 2,
+            StrInt::Large(_) => 1 + 4,
         }
     }
 }
@@ -181,17 +215,17 @@ impl FromBuf for StrInt {
                 Ok(StrInt::Large(buf_to_i32(&src[1..])))
             }
             REDIS_RDB_ENC_LZF => Err(Error::Other),
-            _ => panic!("not exact str int type but got:{}", ltype),
+            _ => Err(Error::Faild("unknown string encoding")),
         }
     }
 }
 
 impl StrInt {
     pub fn value(&self) -> i32 {
-        match self {
-            &StrInt::Small(value) => value as i32,
-            &StrInt::Normal(value) => value as i32,
-            &StrInt::Large(value) => value as i32,
+        match *self {
+            StrInt::Small(value) => value as i32,
+            StrInt::Normal(value) => value as i32,
+            StrInt::Large(value) => value,
         }
     }
 }
@@ -221,8 +255,8 @@ impl<I> FromBuf for RedisList<I>
             items.push(item);
         }
         Ok(RedisList {
-            length: length,
-            items: items,
+            length,
+            items,
         })
     }
 }
@@ -231,7 +265,7 @@ impl<I> Shift for RedisList<I>
     where I: Shift + FromBuf
 {
     fn shift(&self) -> usize {
-        self.length.shift() + self.items.iter().map(|x| x.shift()).fold(0, |acc, x| acc + x)
+        self.length.shift() + self.items.iter().map(|x| x.shift()).sum::<usize>()
     }
 }
 
@@ -256,7 +290,7 @@ impl FromBuf for LinkedListItem {
 #[derive(Clone, Debug)]
 pub struct ZSetItem {
     pub member: RedisString,
-    pub score: RedisString,
+    pub score: StrDouble,
 }
 
 impl Shift for ZSetItem {
@@ -268,25 +302,101 @@ impl Shift for ZSetItem {
 impl FromBuf for ZSetItem {
     fn from_buf(src: &[u8]) -> Result<ZSetItem> {
         let member = RedisString::from_buf(src)?;
-        let score = RedisString::from_buf(&src[member.shift()..])?;
+        let score = StrDouble::from_buf(&src[member.shift()..])?;
         Ok(ZSetItem {
-            member: member,
-            score: score,
+            member,
+            score,
         })
     }
 }
 
+/// a double saved as text behind a one byte length, the lengths 253, 254
+/// and 255 stand for nan, inf and -inf.
+#[derive(Clone, Debug)]
+pub enum StrDouble {
+    Text(Vec<u8>),
+    Nan,
+    PosInf,
+    NegInf,
+}
+
+impl Shift for StrDouble {
+    fn shift(&self) -> usize {
+        match self {
+            StrDouble::Text(text) => 1 + text.len(),
+            _ => 1,
+        }
+    }
+}
+
+impl FromBuf for StrDouble {
+    fn from_buf(src: &[u8]) -> Result<StrDouble> {
+        more!(src.is_empty());
+        match src[0] {
+            REDIS_RDB_DOUBLE_NAN => Ok(StrDouble::Nan),
+            REDIS_RDB_DOUBLE_POS_INF => Ok(StrDouble::PosInf),
+            REDIS_RDB_DOUBLE_NEG_INF => Ok(StrDouble::NegInf),
+            len => {
+                let len = len as usize;
+                more!(src.len() < 1 + len);
+                Ok(StrDouble::Text(src[1..1 + len].to_vec()))
+            }
+        }
+    }
+}
+
+impl StrDouble {
+    pub fn into_data(self) -> Vec<u8> {
+        match self {
+            StrDouble::Text(text) => text,
+            StrDouble::Nan => b"nan".to_vec(),
+            StrDouble::PosInf => b"inf".to_vec(),
+            StrDouble::NegInf => b"-inf".to_vec(),
+        }
+    }
+}
+
+// for zset list with binary scores since rdb version 8
+#[derive(Clone, Debug)]
+pub struct ZSet2Item {
+    pub member: RedisString,
+    pub score: f64,
+}
+
+impl Shift for ZSet2Item {
+    fn shift(&self) -> usize {
+        self.member.shift() + 8
+    }
+}
+
+impl FromBuf for ZSet2Item {
+    fn from_buf(src: &[u8]) -> Result<ZSet2Item> {
+        let member = RedisString::from_buf(src)?;
+        let pos = member.shift();
+        more!(src.len() < pos + 8);
+        let score = buf_to_f64(&src[pos..]);
+        Ok(ZSet2Item {
+            member,
+            score,
+        })
+    }
+}
 
 // for Hash
 #[derive(Clone, Debug)]
 pub struct HashItem {
     pub key: RedisString,
     pub value: RedisString,
+    /// unix time in ms the field expires at, for hashes with field TTLs.
+    pub expire: Option<u64>,
+    // the TTL as saved by HASH_METADATA in front of the field
+    ttl: Option<Length>,
 }
 
 impl Shift for HashItem {
     fn shift(&self) -> usize {
-        self.key.shift() + self.value.shift()
+        self.ttl.as_ref().map(|ttl| ttl.shift()).unwrap_or(0) + self.key.shift() +
+        self.value.shift()
     }
 }
 
@@ -295,73 +405,203 @@ impl FromBuf for HashItem {
         let key = RedisString::from_buf(src)?;
         let value = RedisString::from_buf(&src[key.shift()..])?;
         Ok(HashItem {
-            key: key,
-            value: value,
+            key,
+            value,
+            expire: None,
+            ttl: None,
+        })
+    }
+}
+
+impl HashItem {
+    /// a field with its TTL in front, 0 means no TTL. since 7.4 GA the TTL is
+    /// relative to the minimum expire of the hash, plus one.
+    fn from_buf_with_ttl(src: &[u8], min_expire: Option<u64>) -> Result<Self> {
+        let ttl = Length::from_buf(src)?;
+        let HashItem { key, value, .. } = HashItem::from_buf(&src[ttl.shift()..])?;
+        let expire = match (ttl.value(), min_expire) {
+            (0, _) => None,
+            (ttl, Some(min_expire)) => {
+                let expire = (ttl - 1).checked_add(min_expire);
+                Some(expire.ok_or(Error::Faild("hash field expire overflows"))?)
+            }
+            (ttl, None) => Some(ttl),
+        };
+        Ok(HashItem {
+            key,
+            value,
+            expire,
+            ttl: Some(ttl),
         })
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-pub struct ZipListTail(u32);
+/// A hash with field TTLs, saved as HASH_METADATA.
+#[derive(Clone, Debug)]
+pub struct HashMetadata {
+    /// the earliest field expire, not saved by the 7.4 release candidates.
+    pub min_expire: Option<u64>,
+    pub length: Length,
+    pub items: Vec<HashItem>,
+}
 
-impl Shift for ZipListTail {
+impl Shift for HashMetadata {
     fn shift(&self) -> usize {
-        4
+        self.min_expire.map(|_| 8).unwrap_or(0) + self.length.shift() +
+        self.items.iter().map(|x| x.shift()).sum::<usize>()
     }
 }
 
-impl FromBuf for ZipListTail {
-    fn from_buf(src: &[u8]) -> Result<Self> {
-        more!(src.len() < 4);
-        let val = buf_to_u32(src);
-        Ok(ZipListTail(val))
+impl HashMetadata {
+    pub fn from_buf(src: &[u8], rtype: u8) -> Result<Self> {
+        let mut pos = 0;
+        let min_expire = if rtype == REDIS_RDB_TYPE_HASH_METADATA {
+            more!(src.len() < 8);
+            pos += 8;
+            Some(buf_to_u64(src))
+        } else {
+            None
+        };
+        more!(src.len() <= pos);
+        let length = Length::from_buf(&src[pos..])?;
+        pos += length.shift();
+        let mut items = Vec::new();
+        for _ in 0..length.length() {
+            let item = HashItem::from_buf_with_ttl(&src[pos..], min_expire)?;
+            pos += item.shift();
+            items.push(item);
+        }
+        Ok(HashMetadata {
+            min_expire,
+            length,
+            items,
+        })
     }
 }
 
+/// field, value and the unix time in ms the field expires at.
+pub type HashField = (Vec<u8>, Vec<u8>, Option<u64>);
 
-#[derive(Copy, Clone, Debug)]
-pub struct ZipListLen(u16);
+/// A listpack of field, value, TTL triplets, saved as HASH_LISTPACK_EX.
+#[derive(Clone, Debug)]
+pub struct HashListPackEx {
+    /// the earliest field expire, not saved by the 7.4 release candidates.
+    pub min_expire: Option<u64>,
+    /// fields and values with their absolute TTL.
+    pub fields: Vec<HashField>,
+    shift: usize,
+}
+
+impl Shift for HashListPackEx {
+    fn shift(&self) -> usize {
+        self.shift
+    }
+}
+
+impl HashListPackEx {
+    pub fn from_buf(src: &[u8], rtype: u8) -> Result<Self> {
+        let (min_expire, pos) = if rtype == REDIS_RDB_TYPE_HASH_LISTPACK_EX {
+            more!(src.len() < 8);
+            (Some(buf_to_u64(src)), 8)
+        } else {
+            (None, 0)
+        };
+        let data = RedisString::from_buf(&src[pos..])?;
+        let shift = pos + data.shift();
+        Ok(HashListPackEx {
+            min_expire,
+            fields: HashListPackEx::decode_fields(&data.into_data())?,
+            shift,
+        })
+    }
+
+    /// 0 as TTL in the listpack means none.
+    fn decode_fields(src: &[u8]) -> Result<Vec<HashField>> {
+        let lp = decode_listpack(src)?;
+        let mut fields = Vec::new();
+        let mut iter = lp.entries.into_iter().map(|ListPackEntry { sp, .. }| sp);
+        while let Some(field) = iter.next() {
+            let value = iter.next();
+            let ttl = iter.next();
+            let (value, ttl) = match (value, ttl) {
+                (Some(value), Some(ttl)) => (value, ttl),
+                _ => return Err(Error::Faild("hash listpack with a partial field")),
+            };
+            let ttl = ttl.to_i64().ok_or(Error::Faild("hash listpack TTL is not an integer"))?;
+            let expire = if ttl == 0 { None } else { Some(ttl as u64) };
+            fields.push((field.into_data(), value.into_data(), expire));
+        }
+        Ok(fields)
+    }
+}
+
+// for quicklist v2 nodes
+#[derive(Clone, Debug)]
+pub struct QuickListNode {
+    pub container: Length,
+    pub datas: Vec<Vec<u8>>,
+    shift: usize,
+}
 
-impl Shift for ZipListLen {
+impl Shift for QuickListNode {
     fn shift(&self) -> usize {
-        2
+        self.shift
     }
 }
 
-impl FromBuf for ZipListLen {
+impl FromBuf for QuickListNode {
+    /// PLAIN nodes hold one big element, PACKED nodes hold a listpack.
     fn from_buf(src: &[u8]) -> Result<Self> {
-        more!(src.len() < 2);
-        let val = buf_to_u16(src);
-        Ok(ZipListLen(val))
+        let container = Length::from_buf(src)?;
+        let data = RedisString::from_buf(&src[container.shift()..])?;
+        let shift = container.shift() + data.shift();
+        let datas = match container.length() {
+            REDIS_RDB_QUICKLIST_NODE_CONTAINER_PLAIN => vec![data.into_data()],
+            REDIS_RDB_QUICKLIST_NODE_CONTAINER_PACKED => {
+                decode_listpack(&data.into_data())?.into_data()
+            }
+            _ => return Err(Error::Faild("wrong quicklist node container")),
+        };
+        Ok(QuickListNode {
+            container,
+            datas,
+            shift,
+        })
     }
 }
 
+impl QuickListNode {
+    pub fn into_data(self) -> Vec<Vec<u8>> {
+        self.datas
+    }
+}
 
+/// the length of the previous entry, only its size is needed to walk the
+/// ziplist forward.
 #[derive(Clone, Debug)]
 pub enum ZLELen {
-    Small(u8),
-    Large(u32),
+    Small,
+    Large,
 }
 
 impl Shift for ZLELen {
     fn shift(&self) -> usize {
-        match self {
-            &ZLELen::Small(_) => 1,
-            &ZLELen::Large(_) => 5,
+        match *self {
+            ZLELen::Small => 1,
+            ZLELen::Large => 5,
         }
     }
 }
 
 impl FromBuf for ZLELen {
     fn from_buf(src: &[u8]) -> Result<Self> {
-        more!(src.len() < 1);
+        more!(src.is_empty());
         let flag = src[0];
         if flag <= REDIS_RDB_FLAG_ZIPLIST_ENTRY_LEN_MAX {
-            return Ok(ZLELen::Small(flag));
+            return Ok(ZLELen::Small);
         }
         more!(src.len() < 1 + 4);
-        let value = buf_to_u32(&src[1..]);
-        Ok(ZLELen::Large(value))
+        Ok(ZLELen::Large)
     }
 }
 
@@ -397,15 +637,15 @@ impl ZLESpData {
 impl Shift for ZLESpData {
     fn shift(&self) -> usize {
         match self {
-            &ZLESpData::SmallStr(ref v) => 1 + v.len(),
-            &ZLESpData::NormalStr(ref v) => 2 + v.len(),
-            &ZLESpData::LargeStr(ref v) => 1 + 4 + v.len(),
-            &ZLESpData::ExSmallInt(_) => 1,
-            &ZLESpData::SmallInt(_) => 1 + 1,
-            &ZLESpData::NormalInt(_) => 1 + 2,
-            &ZLESpData::LargeTrimInt(_) => 1 + 3,
-            &ZLESpData::LargeInt(_) => 1 + 4,
-            &ZLESpData::ExLargeInt(_) => 1 + 8,
+            ZLESpData::SmallStr(v) => 1 + v.len(),
+            ZLESpData::NormalStr(v) => 2 + v.len(),
+            ZLESpData::LargeStr(v) => 1 + 4 + v.len(),
+            ZLESpData::ExSmallInt(_) => 1,
+            ZLESpData::SmallInt(_) => 1 + 1,
+            ZLESpData::NormalInt(_) => 1 + 2,
+            ZLESpData::LargeTrimInt(_) => 1 + 3,
+            ZLESpData::LargeInt(_) => 1 + 4,
+            ZLESpData::ExLargeInt(_) => 1 + 8,
         }
     }
 }
@@ -422,7 +662,7 @@ impl ZLESpData {
                 more!(src.len() < 1 + 1);
                 Ok(ZLESpData::SmallInt(src[1] as i8))
             }
-            val if 1 <= val && val <= 13 => Ok(ZLESpData::ExSmallInt(val - 1)),
+            val if (1..=13).contains(&val) => Ok(ZLESpData::ExSmallInt(val - 1)),
             _ => Err(Error::Other),
         }
     }
@@ -455,19 +695,19 @@ impl ZLESpData {
                 let req = 1;
                 let len = (src[0] & 0x3f) as usize;
                 more!(src.len() < req + len);
-                Ok(ZLESpData::SmallStr((&src[req..req + len]).to_vec()))
+                Ok(ZLESpData::SmallStr(src[req..req + len].to_vec()))
             }
             REDIS_RDB_FLAG_ZIPLIST_ENTRY_NORMAL_STR => {
                 let req = 1 + 1;
                 more!(src.len() < req);
-                let len = (buf_to_u16(&src[1..]) & 0x3fff) as usize;
+                let len = (buf_to_u16_big(src) & 0x3fff) as usize;
                 more!(src.len() < req + len);
                 Ok(ZLESpData::NormalStr(src[req..req + len].to_vec()))
             }
             REDIS_RDB_FLAG_ZIPLIST_ENTRY_LARGE_STR => {
                 let req = 1 + 4;
                 more!(src.len() < req);
-                let len = (buf_to_u64(&src[1..]) & 0x3fff_ffff) as usize;
+                let len = buf_to_u32_big(&src[1..]) as usize;
                 more!(src.len() < req + len);
                 Ok(ZLESpData::LargeStr(src[req..req + len].to_vec()))
             }
@@ -478,7 +718,7 @@ impl ZLESpData {
 
 impl FromBuf for ZLESpData {
     fn from_buf(src: &[u8]) -> Result<ZLESpData> {
-        more!(src.len() == 0);
+        more!(src.is_empty());
         choice!(ZLESpData::to_str(src));
         choice!(ZLESpData::to_usual_int(src));
         choice!(ZLESpData::to_special_int(src));
@@ -504,7 +744,7 @@ impl FromBuf for ZipListEntry {
         let sp = ZLESpData::from_buf(&src[len.shift()..])?;
         Ok(ZipListEntry {
             prev_len: len,
-            sp: sp,
+            sp,
         })
     }
 }
@@ -521,7 +761,7 @@ pub struct ZipList {
 impl Shift for ZipList {
     fn shift(&self) -> usize {
         self.zlbytes.shift() + self.zltails.shift() + self.zllen.shift() + self.zlend.shift() +
-        self.entries.iter().map(|x| x.shift()).fold(0, |acc, x| acc + x)
+        self.entries.iter().map(|x| x.shift()).sum::<usize>()
     }
 }
 
@@ -540,29 +780,425 @@ impl FromBuf for ZipList {
             pos += entry.shift();
             entries.push(entry);
         }
+        more!(src.len() <= pos);
         let zlend = src[pos];
-        assert_eq!(zlend, 0xff);
+        faild!(zlend != 0xff, "ziplist end flag not found");
         Ok(ZipList {
-            zlbytes: zlbytes,
-            zltails: zltails,
-            zllen: zllen,
-            entries: entries,
-            zlend: zlend,
+            zlbytes,
+            zltails,
+            zllen,
+            entries,
+            zlend,
         })
     }
 }
 
 
+/// decode a ziplist saved as a string, which is complete once the string
+/// is read, so running out of bytes means it is corrupt.
+fn decode_ziplist(src: &[u8]) -> Result<ZipList> {
+    match ZipList::from_buf(src) {
+        Err(Error::More) => Err(Error::Faild("ziplist longer than its string")),
+        other => other,
+    }
+}
 
-pub trait To<T>
-    where T: Shift + FromBuf
-{
-    fn to(&mut self) -> Result<RedisList<T>>;
+/// A ziplist saved as a string, decoded as soon as it is read, e.g. the
+/// nodes of a quicklist.
+#[derive(Clone, Debug)]
+pub struct ZipListString {
+    pub datas: Vec<Vec<u8>>,
+    shift: usize,
 }
 
-impl To<LinkedListItem> for ZipList {
-    fn to(&mut self) -> Result<RedisList<LinkedListItem>> {
-        unimplemented!()
+impl Shift for ZipListString {
+    fn shift(&self) -> usize {
+        self.shift
+    }
+}
+
+impl FromBuf for ZipListString {
+    fn from_buf(src: &[u8]) -> Result<Self> {
+        let rs = RedisString::from_buf(src)?;
+        let shift = rs.shift();
+        let ZipList { entries, .. } = decode_ziplist(&rs.into_data())?;
+        Ok(ZipListString {
+            datas: entries.into_iter().map(|ZipListEntry { sp, .. }| sp.into_data()).collect(),
+            shift,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ZMLen {
+    Small(u8),
+    Large(u32),
+}
+
+impl Shift for ZMLen {
+    fn shift(&self) -> usize {
+        match *self {
+            ZMLen::Small(_) => 1,
+            ZMLen::Large(_) => 1 + 4,
+        }
+    }
+}
+
+impl FromBuf for ZMLen {
+    /// 0~253 is the length itself, 254 means a 4 bytes length follows.
+    fn from_buf(src: &[u8]) -> Result<Self> {
+        more!(src.is_empty());
+        let flag = src[0];
+        if flag < REDIS_RDB_FLAG_ZIPMAP_BIGLEN {
+            return Ok(ZMLen::Small(flag));
+        }
+        faild!(flag == REDIS_RDB_FLAG_ZIPMAP_END, "unexpected zipmap end");
+        more!(src.len() < 1 + 4);
+        Ok(ZMLen::Large(buf_to_u32(&src[1..])))
+    }
+}
+
+impl ZMLen {
+    pub fn length(&self) -> usize {
+        match *self {
+            ZMLen::Small(val) => val as usize,
+            ZMLen::Large(val) => val as usize,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ZipMapEntry {
+    key_len: ZMLen,
+    pub key: Vec<u8>,
+    value_len: ZMLen,
+    free: u8,
+    pub value: Vec<u8>,
+}
+
+impl Shift for ZipMapEntry {
+    fn shift(&self) -> usize {
+        self.key_len.shift() + self.key.len() + self.value_len.shift() + self.free.shift() +
+        self.value.len() + self.free as usize
+    }
+}
+
+impl FromBuf for ZipMapEntry {
+    fn from_buf(src: &[u8]) -> Result<Self> {
+        let key_len = ZMLen::from_buf(src)?;
+        let mut pos = key_len.shift();
+        more!(src.len() < pos + key_len.length());
+        let key = src[pos..pos + key_len.length()].to_vec();
+        pos += key_len.length();
+
+        let value_len = ZMLen::from_buf(&src[pos..])?;
+        pos += value_len.shift();
+        let free: u8 = FromBuf::from_buf(&src[pos..])?;
+        pos += free.shift();
+        more!(src.len() < pos + value_len.length() + free as usize);
+        let value = src[pos..pos + value_len.length()].to_vec();
+        Ok(ZipMapEntry {
+            key_len,
+            key,
+            value_len,
+            free,
+            value,
+        })
+    }
+}
+
+/// the hash encoding of redis before 2.6, replaced by ziplist since.
+#[derive(Clone, Debug)]
+pub struct ZipMap {
+    zmlen: u8,
+    pub entries: Vec<ZipMapEntry>,
+    zmend: u8,
+}
+
+impl Shift for ZipMap {
+    fn shift(&self) -> usize {
+        self.zmlen.shift() + self.zmend.shift() +
+        self.entries.iter().map(|x| x.shift()).sum::<usize>()
+    }
+}
+
+impl FromBuf for ZipMap {
+    /// zmlen is only a hint when it reaches 254, so read until the end flag.
+    fn from_buf(src: &[u8]) -> Result<Self> {
+        let zmlen: u8 = FromBuf::from_buf(src)?;
+        let mut pos = zmlen.shift();
+        let mut entries = Vec::new();
+        loop {
+            more!(src.len() < pos + 1);
+            if src[pos] == REDIS_RDB_FLAG_ZIPMAP_END {
+                break;
+            }
+            let entry = ZipMapEntry::from_buf(&src[pos..])?;
+            pos += entry.shift();
+            entries.push(entry);
+        }
+        Ok(ZipMap {
+            zmlen,
+            entries,
+            zmend: src[pos],
+        })
+    }
+}
+
+/// decode a zipmap saved as a string, running out of bytes before the end
+/// flag means it is corrupt.
+fn decode_zipmap(src: &[u8]) -> Result<ZipMap> {
+    match ZipMap::from_buf(src) {
+        Err(Error::More) => Err(Error::Faild("zipmap longer than its string")),
+        other => other,
+    }
+}
+
+/// A zipmap saved as a string, decoded as soon as it is read.
+#[derive(Clone, Debug)]
+pub struct ZipMapString {
+    pub entries: Vec<ZipMapEntry>,
+    shift: usize,
+}
+
+impl Shift for ZipMapString {
+    fn shift(&self) -> usize {
+        self.shift
+    }
+}
+
+impl FromBuf for ZipMapString {
+    fn from_buf(src: &[u8]) -> Result<Self> {
+        let rs = RedisString::from_buf(src)?;
+        let shift = rs.shift();
+        Ok(ZipMapString {
+            entries: decode_zipmap(&rs.into_data())?.entries,
+            shift,
+        })
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub enum LPESpData {
+    UInt7(u8),
+    Str6(Vec<u8>),
+    Int13(i16),
+    Str12(Vec<u8>),
+    Str32(Vec<u8>),
+    Int16(i16),
+    Int24(i32),
+    Int32(i32),
+    Int64(i64),
+}
+
+impl LPESpData {
+    pub fn into_data(self) -> Vec<u8> {
+        match self {
+            LPESpData::Str6(v) => v,
+            LPESpData::Str12(v) => v,
+            LPESpData::Str32(v) => v,
+            LPESpData::UInt7(v) => format!("{}", v).into_bytes(),
+            LPESpData::Int13(v) => format!("{}", v).into_bytes(),
+            LPESpData::Int16(v) => format!("{}", v).into_bytes(),
+            LPESpData::Int24(v) => format!("{}", v).into_bytes(),
+            LPESpData::Int32(v) => format!("{}", v).into_bytes(),
+            LPESpData::Int64(v) => format!("{}", v).into_bytes(),
+        }
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        match self {
+            &LPESpData::UInt7(v) => Some(v as i64),
+            &LPESpData::Int13(v) => Some(v as i64),
+            &LPESpData::Int16(v) => Some(v as i64),
+            &LPESpData::Int24(v) => Some(v as i64),
+            &LPESpData::Int32(v) => Some(v as i64),
+            &LPESpData::Int64(v) => Some(v),
+            &LPESpData::Str6(ref v) |
+            &LPESpData::Str12(ref v) |
+            &LPESpData::Str32(ref v) => String::from_utf8_lossy(v).parse().ok(),
+        }
+    }
+}
+
+impl Shift for LPESpData {
+    fn shift(&self) -> usize {
+        match self {
+            LPESpData::UInt7(_) => 1,
+            LPESpData::Str6(v) => 1 + v.len(),
+            LPESpData::Int13(_) => 2,
+            LPESpData::Str12(v) => 2 + v.len(),
+            LPESpData::Str32(v) => 1 + 4 + v.len(),
+            LPESpData::Int16(_) => 1 + 2,
+            LPESpData::Int24(_) => 1 + 3,
+            LPESpData::Int32(_) => 1 + 4,
+            LPESpData::Int64(_) => 1 + 8,
+        }
+    }
+}
+
+impl FromBuf for LPESpData {
+    fn from_buf(src: &[u8]) -> Result<LPESpData> {
+        more!(src.is_empty());
+        let flag = src[0];
+        if flag & 0x80 == 0 {
+            return Ok(LPESpData::UInt7(flag & 0x7f));
+        }
+        if flag & 0xc0 == 0x80 {
+            let len = (flag & 0x3f) as usize;
+            more!(src.len() < 1 + len);
+            return Ok(LPESpData::Str6(src[1..1 + len].to_vec()));
+        }
+        if flag & 0xe0 == 0xc0 {
+            more!(src.len() < 2);
+            let uv = (((flag & 0x1f) as i16) << 8) | src[1] as i16;
+            // 13 bits two's complement
+            return Ok(LPESpData::Int13((uv << 3) >> 3));
+        }
+        if flag & 0xf0 == 0xe0 {
+            more!(src.len() < 2);
+            let len = (((flag & 0x0f) as usize) << 8) | src[1] as usize;
+            more!(src.len() < 2 + len);
+            return Ok(LPESpData::Str12(src[2..2 + len].to_vec()));
+        }
+        match flag {
+            0xf0 => {
+                more!(src.len() < 1 + 4);
+                let len = buf_to_u32(&src[1..]) as usize;
+                more!(src.len() < 1 + 4 + len);
+                Ok(LPESpData::Str32(src[5..5 + len].to_vec()))
+            }
+            0xf1 => {
+                more!(src.len() < 1 + 2);
+                Ok(LPESpData::Int16(buf_to_i16(&src[1..])))
+            }
+            0xf2 => {
+                more!(src.len() < 1 + 3);
+                Ok(LPESpData::Int24(buf_to_i32_trim(&src[1..])))
+            }
+            0xf3 => {
+                more!(src.len() < 1 + 4);
+                Ok(LPESpData::Int32(buf_to_i32(&src[1..])))
+            }
+            0xf4 => {
+                more!(src.len() < 1 + 8);
+                Ok(LPESpData::Int64(buf_to_i64(&src[1..])))
+            }
+            _ => Err(Error::Faild("not regular ListPack encoding")),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ListPackEntry {
+    pub sp: LPESpData,
+    back_len: usize,
+}
+
+impl Shift for ListPackEntry {
+    fn shift(&self) -> usize {
+        self.sp.shift() + self.back_len
+    }
+}
+
+impl FromBuf for ListPackEntry {
+    fn from_buf(src: &[u8]) -> Result<Self> {
+        let sp = LPESpData::from_buf(src)?;
+        // backlen stores the entry size in 7 bits groups
+        let back_len = match sp.shift() {
+            0..=127 => 1,
+            128..=16382 => 2,
+            16383..=2097150 => 3,
+            2097151..=268435454 => 4,
+            _ => 5,
+        };
+        more!(src.len() < sp.shift() + back_len);
+        Ok(ListPackEntry {
+            sp,
+            back_len,
+        })
+    }
+}
+
+/// the successor of ziplist since redis 7.0.
+#[derive(Clone, Debug)]
+pub struct ListPack {
+    total_bytes: u32,
+    num_elements: u16,
+    pub entries: Vec<ListPackEntry>,
+    lpend: u8,
+}
+
+impl Shift for ListPack {
+    fn shift(&self) -> usize {
+        self.total_bytes.shift() + self.num_elements.shift() + self.lpend.shift() +
+        self.entries.iter().map(|x| x.shift()).sum::<usize>()
+    }
+}
+
+impl FromBuf for ListPack {
+    /// num_elements saturates at 65535, so read until the EOF flag.
+    fn from_buf(src: &[u8]) -> Result<Self> {
+        let total_bytes: u32 = FromBuf::from_buf(src)?;
+        more!(src.len() < total_bytes as usize);
+        let num_elements: u16 = FromBuf::from_buf(&src[total_bytes.shift()..])?;
+        let mut pos = total_bytes.shift() + num_elements.shift();
+        let mut entries = Vec::new();
+        loop {
+            more!(src.len() < pos + 1);
+            if src[pos] == REDIS_RDB_FLAG_LISTPACK_EOF {
+                break;
+            }
+            let entry = ListPackEntry::from_buf(&src[pos..])?;
+            pos += entry.shift();
+            entries.push(entry);
+        }
+        Ok(ListPack {
+            total_bytes,
+            num_elements,
+            entries,
+            lpend: src[pos],
+        })
+    }
+}
+
+impl ListPack {
+    pub fn into_data(self) -> Vec<Vec<u8>> {
+        self.entries.into_iter().map(|ListPackEntry { sp, .. }| sp.into_data()).collect()
+    }
+}
+
+/// decode a listpack saved as a string, which is complete once the string
+/// is read, so running out of bytes means it is corrupt.
+fn decode_listpack(src: &[u8]) -> Result<ListPack> {
+    match ListPack::from_buf(src) {
+        Err(Error::More) => Err(Error::Faild("listpack longer than its string")),
+        other => other,
+    }
+}
+
+/// A listpack saved as a string, decoded as soon as it is read.
+#[derive(Clone, Debug)]
+pub struct ListPackString {
+    pub datas: Vec<Vec<u8>>,
+    shift: usize,
+}
+
+impl Shift for ListPackString {
+    fn shift(&self) -> usize {
+        self.shift
+    }
+}
+
+impl FromBuf for ListPackString {
+    fn from_buf(src: &[u8]) -> Result<Self> {
+        let rs = RedisString::from_buf(src)?;
+        let shift = rs.shift();
+        Ok(ListPackString {
+            datas: decode_listpack(&rs.into_data())?.into_data(),
+            shift,
+        })
     }
 }
 
@@ -595,10 +1231,10 @@ impl FromBuf for IntSetEncoding {
 
 impl IntSetEncoding {
     pub fn encoding(&self) -> usize {
-        match self {
-            &IntSetEncoding::Normal => 2,
-            &IntSetEncoding::Large => 4,
-            &IntSetEncoding::ExLarge => 8,
+        match *self {
+            IntSetEncoding::Normal => 2,
+            IntSetEncoding::Large => 4,
+            IntSetEncoding::ExLarge => 8,
         }
     }
 }
@@ -659,9 +1295,9 @@ impl FromBuf for IntSet {
             pos += e;
         }
         Ok(IntSet {
-            encoding: encoding,
-            count: count,
-            ints: ints,
+            encoding,
+            count,
+            ints,
         })
     }
 }
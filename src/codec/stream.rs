@@ -0,0 +1,273 @@
+use com::*;
+use consts::*;
+use super::{Length, RedisString, ListPack, ListPackEntry, LPESpData};
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    /// 128 bits big endian id, as used by rax keys and PEL entries.
+    fn from_raw(src: &[u8]) -> Result<StreamId> {
+        faild!(src.len() < 16, "stream id shorter than 16 bytes");
+        Ok(StreamId {
+            ms: buf_to_u64_big(src),
+            seq: buf_to_u64_big(&src[8..]),
+        })
+    }
+
+    fn from_lengths(src: &[u8], pos: &mut usize) -> Result<StreamId> {
+        let ms = read_length(src, pos)?;
+        let seq = read_length(src, pos)?;
+        Ok(StreamId { ms, seq })
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamEntry {
+    pub id: StreamId,
+    pub fields: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// a pending entry of a consumer group.
+#[derive(Clone, Debug)]
+pub struct StreamNack {
+    pub id: StreamId,
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamConsumer {
+    pub name: Vec<u8>,
+    pub seen_time: u64,
+    /// only saved since `RDB_TYPE_STREAM_LISTPACKS_3`.
+    pub active_time: Option<u64>,
+    pub pending: Vec<StreamId>,
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamGroup {
+    pub name: Vec<u8>,
+    pub last_id: StreamId,
+    /// only saved since `RDB_TYPE_STREAM_LISTPACKS_2`.
+    pub entries_read: Option<u64>,
+    pub pending: Vec<StreamNack>,
+    pub consumers: Vec<StreamConsumer>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RedisStream {
+    /// entries not flagged as deleted, in id order.
+    pub entries: Vec<StreamEntry>,
+    pub length: u64,
+    pub last_id: StreamId,
+    /// `first_id`, `max_deleted_id` and `entries_added` are only saved since
+    /// `RDB_TYPE_STREAM_LISTPACKS_2`.
+    pub first_id: Option<StreamId>,
+    pub max_deleted_id: Option<StreamId>,
+    pub entries_added: Option<u64>,
+    pub groups: Vec<StreamGroup>,
+    shift: usize,
+}
+
+impl Shift for RedisStream {
+    fn shift(&self) -> usize {
+        self.shift
+    }
+}
+
+impl RedisStream {
+    /// `rtype` tells which of the three stream layouts follows.
+    pub fn from_buf(src: &[u8], rtype: u8) -> Result<RedisStream> {
+        let v2 = rtype != REDIS_RDB_TYPE_STREAM_LISTPACKS;
+        let v3 = rtype == REDIS_RDB_TYPE_STREAM_LISTPACKS_3;
+        let mut pos = 0;
+
+        let mut entries = Vec::new();
+        let nodes = read_length(src, &mut pos)?;
+        for _ in 0..nodes {
+            let master_id = read_string(src, &mut pos)?;
+            let master_id = StreamId::from_raw(&master_id)?;
+            let lp = read_string(src, &mut pos)?;
+            let lp = ListPack::from_buf(&lp)?;
+            read_listpack_entries(master_id, lp, &mut entries)?;
+        }
+
+        let length = read_length(src, &mut pos)?;
+        let last_id = StreamId::from_lengths(src, &mut pos)?;
+        let (first_id, max_deleted_id, entries_added) = if v2 {
+            let first_id = StreamId::from_lengths(src, &mut pos)?;
+            let max_deleted_id = StreamId::from_lengths(src, &mut pos)?;
+            let entries_added = read_length(src, &mut pos)?;
+            (Some(first_id), Some(max_deleted_id), Some(entries_added))
+        } else {
+            (None, None, None)
+        };
+
+        let mut groups = Vec::new();
+        let group_count = read_length(src, &mut pos)?;
+        for _ in 0..group_count {
+            let name = read_string(src, &mut pos)?;
+            let group_last_id = StreamId::from_lengths(src, &mut pos)?;
+            let entries_read = if v2 {
+                Some(read_length(src, &mut pos)?)
+            } else {
+                None
+            };
+
+            let mut pending = Vec::new();
+            let pending_count = read_length(src, &mut pos)?;
+            for _ in 0..pending_count {
+                let id = read_id(src, &mut pos)?;
+                let delivery_time = read_ms(src, &mut pos)?;
+                let delivery_count = read_length(src, &mut pos)?;
+                pending.push(StreamNack {
+                    id,
+                    delivery_time,
+                    delivery_count,
+                });
+            }
+
+            let mut consumers = Vec::new();
+            let consumer_count = read_length(src, &mut pos)?;
+            for _ in 0..consumer_count {
+                let consumer_name = read_string(src, &mut pos)?;
+                let seen_time = read_ms(src, &mut pos)?;
+                let active_time = if v3 {
+                    Some(read_ms(src, &mut pos)?)
+                } else {
+                    None
+                };
+                let mut consumer_pending = Vec::new();
+                let consumer_pending_count = read_length(src, &mut pos)?;
+                for _ in 0..consumer_pending_count {
+                    consumer_pending.push(read_id(src, &mut pos)?);
+                }
+                consumers.push(StreamConsumer {
+                    name: consumer_name,
+                    seen_time,
+                    active_time,
+                    pending: consumer_pending,
+                });
+            }
+
+            groups.push(StreamGroup {
+                name,
+                last_id: group_last_id,
+                entries_read,
+                pending,
+                consumers,
+            });
+        }
+
+        Ok(RedisStream {
+            entries,
+            length,
+            last_id,
+            first_id,
+            max_deleted_id,
+            entries_added,
+            groups,
+            shift: pos,
+        })
+    }
+}
+
+/// a listpack of the stream rax starts with the master entry:
+/// `count, deleted, master fields count, master fields..., 0`,
+/// then every entry is `flags, ms diff, seq diff, [fields count],
+/// [field,] value..., lp-count`.
+fn read_listpack_entries(master_id: StreamId,
+                         lp: ListPack,
+                         entries: &mut Vec<StreamEntry>)
+                         -> Result<()> {
+    let mut iter = lp.entries.into_iter().map(|ListPackEntry { sp, .. }| sp);
+    let count = lp_int(iter.next())?;
+    let deleted = lp_int(iter.next())?;
+    let master_field_count = lp_int(iter.next())?;
+    let mut master_fields = Vec::new();
+    for _ in 0..master_field_count {
+        master_fields.push(lp_data(iter.next())?);
+    }
+    // the master entry terminator
+    lp_int(iter.next())?;
+
+    for _ in 0..count + deleted {
+        let flags = lp_int(iter.next())?;
+        let ms = lp_int(iter.next())? as u64;
+        let seq = lp_int(iter.next())? as u64;
+        let id = StreamId {
+            ms: master_id.ms.wrapping_add(ms),
+            seq: master_id.seq.wrapping_add(seq),
+        };
+        let mut fields = Vec::new();
+        if flags & REDIS_RDB_STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+            for field in &master_fields {
+                fields.push((field.clone(), lp_data(iter.next())?));
+            }
+        } else {
+            let field_count = lp_int(iter.next())?;
+            for _ in 0..field_count {
+                let field = lp_data(iter.next())?;
+                fields.push((field, lp_data(iter.next())?));
+            }
+        }
+        // lp-count, the number of listpack items of this entry
+        lp_int(iter.next())?;
+        if flags & REDIS_RDB_STREAM_ITEM_FLAG_DELETED == 0 {
+            entries.push(StreamEntry {
+                id,
+                fields,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn lp_data(sp: Option<LPESpData>) -> Result<Vec<u8>> {
+    sp.map(|sp| sp.into_data()).ok_or(Error::Faild("stream listpack ended too early"))
+}
+
+fn lp_int(sp: Option<LPESpData>) -> Result<i64> {
+    sp.as_ref()
+        .and_then(|sp| sp.to_i64())
+        .ok_or(Error::Faild("stream listpack integer expected"))
+}
+
+fn read_length(src: &[u8], pos: &mut usize) -> Result<u64> {
+    more!(src.len() <= *pos);
+    let length = Length::from_buf(&src[*pos..])?;
+    *pos += length.shift();
+    Ok(length.value())
+}
+
+fn read_string(src: &[u8], pos: &mut usize) -> Result<Vec<u8>> {
+    more!(src.len() <= *pos);
+    let rs = RedisString::from_buf(&src[*pos..])?;
+    *pos += rs.shift();
+    Ok(rs.into_data())
+}
+
+fn read_id(src: &[u8], pos: &mut usize) -> Result<StreamId> {
+    more!(src.len() < *pos + 16);
+    let id = StreamId::from_raw(&src[*pos..])?;
+    *pos += 16;
+    Ok(id)
+}
+
+fn read_ms(src: &[u8], pos: &mut usize) -> Result<u64> {
+    more!(src.len() < *pos + 8);
+    let ms = buf_to_u64(&src[*pos..]);
+    *pos += 8;
+    Ok(ms)
+}
@@ -8,7 +8,6 @@ use std::num::ParseFloatError;
 use byteorder::{BigEndian, LittleEndian, ByteOrder};
 
 pub trait Shift {
-    #[inline]
     fn shift(&self) -> usize;
 }
 
@@ -94,7 +93,7 @@ impl FromBuf for u8
     where Self: Sized
 {
     fn from_buf(src: &[u8]) -> Result<Self> {
-        more!(src.len() < 1);
+        more!(src.is_empty());
         Ok(src[0])
     }
 }
@@ -111,6 +110,13 @@ pub enum Error {
     IoError(io::Error),
     FromUtf8Error(FromUtf8Error),
     ParseFloatError(ParseFloatError),
+    /// the CRC64 footer does not match the checksum of the parsed bytes.
+    Checksum { expected: u64, computed: u64 },
+    /// the rdb version is out of the range newbee can parse.
+    UnsupportedVersion(u32),
+    /// a value type or opcode that is unknown, or that can't appear in a rdb
+    /// of this version.
+    UnsupportedType { code: u8, version: u32 },
 }
 
 impl From<io::Error> for Error {
@@ -145,10 +151,11 @@ pub fn buf_to_i32(src: &[u8]) -> i32 {
 #[inline]
 pub fn buf_to_i32_trim(src: &[u8]) -> i32 {
     let mut vi32 = 0i32;
-    vi32 |= (src[0] as i32) << 0;
+    vi32 |= src[0] as i32;
     vi32 |= (src[1] as i32) << 8;
     vi32 |= (src[2] as i32) << 16;
-    vi32
+    // sign extend the 24 bit integer
+    (vi32 << 8) >> 8
 }
 
 #[inline]
@@ -167,11 +174,26 @@ pub fn buf_to_u64(src: &[u8]) -> u64 {
     LittleEndian::read_u64(src)
 }
 
+#[inline]
+pub fn buf_to_u64_big(src: &[u8]) -> u64 {
+    BigEndian::read_u64(src)
+}
+
 #[inline]
 pub fn buf_to_i64(src: &[u8]) -> i64 {
     LittleEndian::read_i64(src)
 }
 
+#[inline]
+pub fn buf_to_f64(src: &[u8]) -> f64 {
+    LittleEndian::read_f64(src)
+}
+
+#[inline]
+pub fn buf_to_f32(src: &[u8]) -> f32 {
+    LittleEndian::read_f32(src)
+}
+
 #[inline]
 pub fn buf_to_u16(src: &[u8]) -> u16 {
     LittleEndian::read_u16(src)
@@ -191,3 +213,64 @@ pub fn buf_to_i16(src: &[u8]) -> i16 {
 pub fn min<T: PartialOrd + Copy>(lhs: T, rhs: T) -> T {
     if lhs > rhs { rhs } else { lhs }
 }
+
+/// format a double the way redis replies it: `inf`, `-inf`, or the shortest
+/// digits that round-trip, in exponent form like `%g` for very large or
+/// very small values.
+pub fn fmt_double(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_owned();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_owned();
+    }
+    let sci = format!("{:e}", value);
+    let (mantissa, exp) = sci.split_at(sci.find('e').expect("exponent is always present"));
+    let exp: i32 = exp[1..].parse().expect("exponent is always a number");
+    if !(-4..17).contains(&exp) {
+        let sign = if exp < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", mantissa, sign, exp.abs())
+    } else {
+        format!("{}", value)
+    }
+}
+
+// CRC-64/Jones used by redis: polynomial 0xad93d23594c935a9, reflected
+// here as 0x95ac9329ac4bc9b5, zero initial value and no final xor.
+const CRC64_POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+const fn crc64_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ CRC64_POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC64_TABLE: [u64; 256] = crc64_table();
+
+/// continue the checksum `crc` over `src`, start with 0.
+pub fn crc64(mut crc: u64, src: &[u8]) -> u64 {
+    for &byte in src {
+        crc = CRC64_TABLE[((crc ^ byte as u64) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crc64;
+
+    #[test]
+    fn test_crc64_check() {
+        assert_eq!(crc64(0, b"123456789"), 0xe9c6d914c4b8d9ca);
+    }
+}
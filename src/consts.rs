@@ -1,6 +1,8 @@
 pub const REDIS_RDB_6BITLEN: u8 = 0b0;
 pub const REDIS_RDB_14BITLEN: u8 = 0b01;
 pub const REDIS_RDB_32BITLEN: u8 = 0b10;
+pub const REDIS_RDB_32BITLEN_FLAG: u8 = 0x80;
+pub const REDIS_RDB_64BITLEN_FLAG: u8 = 0x81;
 pub const REDIS_RDB_ENCVAL: u8 = 0b11;
 
 // pub const REDIS_RDB_LENERR: u32 = 111;
@@ -15,6 +17,9 @@ pub const REDIS_RDB_TYPE_LIST: u8 = 1;
 pub const REDIS_RDB_TYPE_SET: u8 = 2;
 pub const REDIS_RDB_TYPE_ZSET: u8 = 3;
 pub const REDIS_RDB_TYPE_HASH: u8 = 4;
+pub const REDIS_RDB_TYPE_ZSET_2: u8 = 5;
+pub const REDIS_RDB_TYPE_MODULE: u8 = 6;
+pub const REDIS_RDB_TYPE_MODULE_2: u8 = 7;
 
 // Object types for encoded objects.
 pub const REDIS_RDB_TYPE_HASH_ZIPMAP: u8 = 9;
@@ -22,6 +27,20 @@ pub const REDIS_RDB_TYPE_LIST_ZIPLIST: u8 = 10;
 pub const REDIS_RDB_TYPE_SET_INTSET: u8 = 11;
 pub const REDIS_RDB_TYPE_ZSET_ZIPLIST: u8 = 12;
 pub const REDIS_RDB_TYPE_HASH_ZIPLIST: u8 = 13;
+pub const REDIS_RDB_TYPE_LIST_QUICKLIST: u8 = 14;
+pub const REDIS_RDB_TYPE_STREAM_LISTPACKS: u8 = 15;
+pub const REDIS_RDB_TYPE_HASH_LISTPACK: u8 = 16;
+pub const REDIS_RDB_TYPE_ZSET_LISTPACK: u8 = 17;
+pub const REDIS_RDB_TYPE_LIST_QUICKLIST_2: u8 = 18;
+pub const REDIS_RDB_TYPE_STREAM_LISTPACKS_2: u8 = 19;
+pub const REDIS_RDB_TYPE_SET_LISTPACK: u8 = 20;
+pub const REDIS_RDB_TYPE_STREAM_LISTPACKS_3: u8 = 21;
+// hashes with field TTLs, the redis 7.4 release candidates did not save the
+// minimum expire of the hash in front of them
+pub const REDIS_RDB_TYPE_HASH_METADATA_PRE_GA: u8 = 22;
+pub const REDIS_RDB_TYPE_HASH_LISTPACK_EX_PRE_GA: u8 = 23;
+pub const REDIS_RDB_TYPE_HASH_METADATA: u8 = 24;
+pub const REDIS_RDB_TYPE_HASH_LISTPACK_EX: u8 = 25;
 
 // Special RDB opcodes (saved/loaded with rdbSaveType/rdbLoadType).
 pub const REDIS_RDB_OPCODE_EXPIRETIME_MS: u8 = 252;
@@ -30,11 +49,24 @@ pub const REDIS_RDB_OPCODE_EXPIRETIME_MS_LEN: usize = 8;
 pub const REDIS_RDB_OPCODE_EXPIRETIME: u8 = 253;
 pub const REDIS_RDB_OPCODE_EXPIRETIME_LEN: usize = 4;
 
+pub const REDIS_RDB_OPCODE_SLOT_INFO: u8 = 0xF4;
+pub const REDIS_RDB_OPCODE_FUNCTION2: u8 = 0xF5;
+pub const REDIS_RDB_OPCODE_FUNCTION_PRE_GA: u8 = 0xF6;
+pub const REDIS_RDB_OPCODE_MODULE_AUX: u8 = 0xF7;
+pub const REDIS_RDB_OPCODE_IDLE: u8 = 0xF8;
+pub const REDIS_RDB_OPCODE_FREQ: u8 = 0xF9;
+pub const REDIS_RDB_OPCODE_AUX: u8 = 0xFA;
+pub const REDIS_RDB_OPCODE_RESIZEDB: u8 = 0xFB;
 pub const REDIS_RDB_OPCODE_SELECTDB: u8 = 0xFE;
+pub const REDIS_RDB_OPCODE_EOF: u8 = 0xFF;
+
+// CRC64 footer, written since rdb version 5
+pub const REDIS_RDB_CHECKSUM_LEN: usize = 8;
+pub const REDIS_RDB_CHECKSUM_VERSION: u32 = 5;
 pub const REDIS_RDB_FLAG_ZIPLIST_ENTRY_LEN_MAX: u8 = 253;
 
 pub const REDIS_RDB_FLAG_ZIPLIST_ENTRY_SMALL_STR: u8 = 0b00;
-pub const REDIS_RDB_FLAG_ZIPLIST_ENTRY_NORMAL_STR: u8 = 001;
+pub const REDIS_RDB_FLAG_ZIPLIST_ENTRY_NORMAL_STR: u8 = 0b01;
 pub const REDIS_RDB_FLAG_ZIPLIST_ENTRY_LARGE_STR: u8 = 0b10;
 
 pub const REDIS_RDB_FLAG_ZIPLIST_ENTRY_NORMAL_INT: u8 = 0b00;
@@ -44,4 +76,35 @@ pub const REDIS_RDB_FLAG_ZIPLIST_ENTRY_EXLARGE_INT: u8 = 0b10;
 pub const REDIS_RDB_FLAG_ZIPLIST_ENTRY_LARGE_TRIM_INT: u8 = 0b0000;
 pub const REDIS_RDB_FLAG_ZIPLIST_ENTRY_SMALL_INT: u8 = 0b1110;
 
+// lengths of the special values of a double saved as text
+pub const REDIS_RDB_DOUBLE_NAN: u8 = 253;
+pub const REDIS_RDB_DOUBLE_POS_INF: u8 = 254;
+pub const REDIS_RDB_DOUBLE_NEG_INF: u8 = 255;
+
+pub const REDIS_RDB_FLAG_ZIPMAP_BIGLEN: u8 = 254;
+pub const REDIS_RDB_FLAG_ZIPMAP_END: u8 = 255;
+
+pub const REDIS_RDB_FLAG_LISTPACK_EOF: u8 = 0xFF;
+
+pub const REDIS_RDB_QUICKLIST_NODE_CONTAINER_PLAIN: usize = 1;
+pub const REDIS_RDB_QUICKLIST_NODE_CONTAINER_PACKED: usize = 2;
+
+pub const REDIS_RDB_STREAM_ITEM_FLAG_DELETED: i64 = 1;
+pub const REDIS_RDB_STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+// opcodes tagging every field of a RDB_TYPE_MODULE_2 value
+pub const REDIS_RDB_MODULE_OPCODE_EOF: u8 = 0;
+pub const REDIS_RDB_MODULE_OPCODE_SINT: u8 = 1;
+pub const REDIS_RDB_MODULE_OPCODE_UINT: u8 = 2;
+pub const REDIS_RDB_MODULE_OPCODE_FLOAT: u8 = 3;
+pub const REDIS_RDB_MODULE_OPCODE_DOUBLE: u8 = 4;
+pub const REDIS_RDB_MODULE_OPCODE_STRING: u8 = 5;
+
+pub const REDIS_MODULE_TYPE_NAME_CHARSET: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
 pub const REDIS_MAGIC_STRING: &str = "REDIS";
+
+// rdb versions newbee can parse, from redis 2.x up to redis 7.4 and valkey 8
+pub const REDIS_RDB_VERSION_MIN: u32 = 1;
+pub const REDIS_RDB_VERSION_MAX: u32 = 12;
@@ -0,0 +1,223 @@
+use com::*;
+use consts::*;
+use codec::*;
+use entry::Value;
+use fmt::RedisFmt;
+use types::{Key, ExpireTime, Eviction};
+use visitor::{RdbVisitor, parse_score};
+use std::mem;
+
+/// A plain collection being decoded element by element, with the chunk of
+/// elements not handed over yet.
+#[derive(Debug)]
+pub struct Elements {
+    pub db: usize,
+    pub key: Key,
+    pub rtype: u8,
+    pub expire: ExpireTime,
+    pub eviction: Eviction,
+    /// items left to decode, quicklist nodes count as one item.
+    pub remaining: usize,
+    pub chunk: Value,
+    /// whether a chunk was handed over, an empty collection has none.
+    pub emitted: bool,
+}
+
+impl Elements {
+    /// `rtype` must be one of the plain collections, see `is_collection`.
+    pub fn new(db: usize,
+               key: Key,
+               rtype: u8,
+               expire: ExpireTime,
+               eviction: Eviction,
+               length: usize)
+               -> Elements {
+        Elements {
+            db,
+            key,
+            rtype,
+            expire,
+            eviction,
+            remaining: length,
+            chunk: empty_value(rtype).expect("a plain collection"),
+            emitted: false,
+        }
+    }
+
+    /// decode the next item at the head of `src` into the chunk.
+    pub fn push(&mut self, src: &[u8]) -> Result<usize> {
+        let shift = match (self.rtype, &mut self.chunk) {
+            (REDIS_RDB_TYPE_LIST, &mut Value::List(ref mut values)) => {
+                let value = RedisString::from_buf(src)?;
+                let shift = value.shift();
+                values.push(value.into_data());
+                shift
+            }
+            (REDIS_RDB_TYPE_LIST_QUICKLIST, &mut Value::List(ref mut values)) => {
+                let node = ZipListString::from_buf(src)?;
+                let shift = node.shift();
+                values.extend(node.datas);
+                shift
+            }
+            (REDIS_RDB_TYPE_LIST_QUICKLIST_2, &mut Value::List(ref mut values)) => {
+                let node = QuickListNode::from_buf(src)?;
+                let shift = node.shift();
+                values.extend(node.into_data());
+                shift
+            }
+            (REDIS_RDB_TYPE_SET, &mut Value::Set(ref mut members)) => {
+                let member = RedisString::from_buf(src)?;
+                let shift = member.shift();
+                members.push(member.into_data());
+                shift
+            }
+            (REDIS_RDB_TYPE_ZSET, &mut Value::ZSet(ref mut pairs)) => {
+                let item = ZSetItem::from_buf(src)?;
+                let shift = item.shift();
+                let ZSetItem { member, score } = item;
+                pairs.push((member.into_data(), parse_score(&score.into_data())?));
+                shift
+            }
+            (REDIS_RDB_TYPE_ZSET_2, &mut Value::ZSet(ref mut pairs)) => {
+                let item = ZSet2Item::from_buf(src)?;
+                let shift = item.shift();
+                pairs.push((item.member.into_data(), item.score));
+                shift
+            }
+            (REDIS_RDB_TYPE_HASH, &mut Value::Hash(ref mut fields)) => {
+                let item = HashItem::from_buf(src)?;
+                let shift = item.shift();
+                fields.push((item.key.into_data(), item.value.into_data(), None));
+                shift
+            }
+            _ => unreachable!(),
+        };
+        self.remaining -= 1;
+        Ok(shift)
+    }
+
+    pub fn chunk_len(&self) -> usize {
+        match &self.chunk {
+            Value::List(values) => values.len(),
+            Value::Set(members) => members.len(),
+            Value::ZSet(pairs) => pairs.len(),
+            Value::Hash(fields) => fields.len(),
+            _ => 0,
+        }
+    }
+
+    /// hand over the elements decoded so far, the chunk starts over empty.
+    pub fn take_chunk(&mut self) -> Value {
+        let empty = empty_value(self.rtype).expect("a plain collection");
+        self.emitted = true;
+        mem::replace(&mut self.chunk, empty)
+    }
+}
+
+/// lists, sets, sorted sets and hashes saved item by item.
+pub fn is_collection(rtype: u8) -> bool {
+    empty_value(rtype).is_some()
+}
+
+fn empty_value(rtype: u8) -> Option<Value> {
+    match rtype {
+        REDIS_RDB_TYPE_LIST |
+        REDIS_RDB_TYPE_LIST_QUICKLIST |
+        REDIS_RDB_TYPE_LIST_QUICKLIST_2 => Some(Value::List(Vec::new())),
+        REDIS_RDB_TYPE_SET => Some(Value::Set(Vec::new())),
+        REDIS_RDB_TYPE_ZSET | REDIS_RDB_TYPE_ZSET_2 => Some(Value::ZSet(Vec::new())),
+        REDIS_RDB_TYPE_HASH => Some(Value::Hash(Vec::new())),
+        _ => None,
+    }
+}
+
+/// one command adding a chunk of elements, the same command a whole key is
+/// rebuilt with.
+pub fn fmt_chunk(key: Vec<u8>, chunk: Value, buf: &mut Vec<RedisFmt>) -> usize {
+    match chunk {
+        Value::List(values) => {
+            buf.push(RedisFmt::Cmd("RPUSH"));
+            buf.push(RedisFmt::Raw(key));
+            for value in values {
+                buf.push(RedisFmt::Raw(value));
+            }
+        }
+        Value::Set(members) => {
+            buf.push(RedisFmt::Cmd("SADD"));
+            buf.push(RedisFmt::Raw(key));
+            for member in members {
+                buf.push(RedisFmt::Raw(member));
+            }
+        }
+        Value::ZSet(pairs) => {
+            buf.push(RedisFmt::Cmd("ZADD"));
+            buf.push(RedisFmt::Raw(key));
+            for (member, score) in pairs {
+                buf.push(RedisFmt::Raw(fmt_double(score).into_bytes()));
+                buf.push(RedisFmt::Raw(member));
+            }
+        }
+        Value::Hash(fields) => {
+            buf.push(RedisFmt::Cmd("HSET"));
+            buf.push(RedisFmt::Raw(key));
+            for (field, value, _) in fields {
+                buf.push(RedisFmt::Raw(field));
+                buf.push(RedisFmt::Raw(value));
+            }
+        }
+        _ => return 0,
+    }
+    buf.push(RedisFmt::CRLF);
+    1
+}
+
+pub fn visit_start<V: RdbVisitor>(key: &[u8],
+                                  rtype: u8,
+                                  length: usize,
+                                  expire: ExpireTime,
+                                  visitor: &mut V) {
+    let expire = expire.unix_ms();
+    match rtype {
+        REDIS_RDB_TYPE_SET => visitor.start_set(key, length, expire),
+        REDIS_RDB_TYPE_ZSET | REDIS_RDB_TYPE_ZSET_2 => {
+            visitor.start_sorted_set(key, length, expire)
+        }
+        REDIS_RDB_TYPE_HASH => visitor.start_hash(key, length, expire),
+        _ => visitor.start_list(key, expire),
+    }
+}
+
+pub fn visit_chunk<V: RdbVisitor>(key: &[u8], chunk: &Value, visitor: &mut V) {
+    match chunk {
+        Value::List(values) => {
+            for value in values {
+                visitor.rpush(key, value);
+            }
+        }
+        Value::Set(members) => {
+            for member in members {
+                visitor.sadd(key, member);
+            }
+        }
+        Value::ZSet(pairs) => {
+            for &(ref member, score) in pairs {
+                visitor.zadd(key, score, member);
+            }
+        }
+        Value::Hash(fields) => {
+            for &(ref field, ref value, field_expire) in fields {
+                visitor.hset(key, field, value, field_expire);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn visit_end<V: RdbVisitor>(key: &[u8], rtype: u8, visitor: &mut V) {
+    match rtype {
+        REDIS_RDB_TYPE_SET => visitor.end_set(key),
+        REDIS_RDB_TYPE_ZSET | REDIS_RDB_TYPE_ZSET_2 => visitor.end_sorted_set(key),
+        REDIS_RDB_TYPE_HASH => visitor.end_hash(key),
+        _ => visitor.end_list(key),
+    }
+}
@@ -0,0 +1,197 @@
+use com::*;
+use codec::*;
+use types::*;
+use module::{ModuleAux, ModuleValue};
+use meta::SlotInfo;
+use visitor::parse_score;
+
+/// A typed event of a rdb file, in the order it is saved.
+#[derive(Clone, Debug)]
+pub enum Entry {
+    /// rdb format version read from `REDISxxxx`.
+    Version(u32),
+    /// an AUX (0xFA) field.
+    Aux { key: Vec<u8>, value: Vec<u8> },
+    /// SELECTDB (0xFE), the following keys belong to this database.
+    SelectDb(usize),
+    /// RESIZEDB (0xFB), the sizes of the current database.
+    ResizeDb { db_size: usize, expires_size: usize },
+    SlotInfo(SlotInfo),
+    ModuleAux(ModuleAux),
+    /// the source of a function library.
+    Function(Vec<u8>),
+    Key(KeyEntry),
+    /// some of the elements of a key streamed element by element, see
+    /// `DefaultRdbParser::set_element_chunk`. the chunks of a key follow
+    /// each other.
+    Chunk(KeyEntry),
+}
+
+/// A key with its value and everything saved in front of it.
+#[derive(Clone, Debug)]
+pub struct KeyEntry {
+    pub db: usize,
+    pub key: Vec<u8>,
+    /// unix time in ms the key expires at.
+    pub expire: Option<u64>,
+    /// seconds since the last access, under an LRU maxmemory-policy.
+    pub idle: Option<u64>,
+    /// logarithmic access counter, under an LFU maxmemory-policy.
+    pub freq: Option<u8>,
+    pub value: Value,
+}
+
+/// A decoded value, whatever encoding it was saved with.
+#[derive(Clone, Debug)]
+pub enum Value {
+    String(Vec<u8>),
+    List(Vec<Vec<u8>>),
+    Set(Vec<Vec<u8>>),
+    ZSet(Vec<(Vec<u8>, f64)>),
+    /// fields with their expire time, for hashes with field TTLs.
+    Hash(Vec<HashField>),
+    Stream(RedisStream),
+    Module {
+        name: String,
+        encver: u32,
+        /// `None` when no handler is registered for the type.
+        value: Option<ModuleValue>,
+    },
+}
+
+impl KeyEntry {
+    pub fn new(db: usize,
+               expire: ExpireTime,
+               eviction: Eviction,
+               data: RedisData)
+               -> Result<KeyEntry> {
+        let key = data.copy_key().into_data();
+        let value = Value::from_data(data)?;
+        Ok(KeyEntry::with_value(db, key, expire, eviction, value))
+    }
+
+    pub fn with_value(db: usize,
+                      key: Vec<u8>,
+                      expire: ExpireTime,
+                      eviction: Eviction,
+                      value: Value)
+                      -> KeyEntry {
+        let (idle, freq) = match eviction {
+            Eviction::Idle(idle) => (Some(idle.value()), None),
+            Eviction::Freq(freq) => (None, Some(freq)),
+            Eviction::None => (None, None),
+        };
+        KeyEntry {
+            db,
+            key,
+            expire: expire.unix_ms(),
+            idle,
+            freq,
+            value,
+        }
+    }
+}
+
+impl Value {
+    /// decode the embedded ziplists, listpacks and intsets of `data`.
+    pub fn from_data(data: RedisData) -> Result<Value> {
+        let value = match data {
+            RedisData::String(_, rs) => Value::String(rs.into_data()),
+            RedisData::List(_, RedisList { items, .. }) => {
+                Value::List(items.into_iter().map(|LinkedListItem(rs)| rs.into_data()).collect())
+            }
+            RedisData::Set(_, RedisList { items, .. }) => {
+                Value::Set(items.into_iter().map(|LinkedListItem(rs)| rs.into_data()).collect())
+            }
+            RedisData::ZSet(_, RedisList { items, .. }) => {
+                let mut pairs = Vec::new();
+                for ZSetItem { member, score } in items {
+                    pairs.push((member.into_data(), parse_score(&score.into_data())?));
+                }
+                Value::ZSet(pairs)
+            }
+            RedisData::ZSet2(_, RedisList { items, .. }) => {
+                Value::ZSet(items.into_iter()
+                    .map(|ZSet2Item { member, score }| (member.into_data(), score))
+                    .collect())
+            }
+            RedisData::Hash(_, RedisList { items, .. }) |
+            RedisData::HashMetadata(_, HashMetadata { items, .. }) => {
+                Value::Hash(items.into_iter()
+                    .map(|HashItem { key, value, expire, .. }| {
+                        (key.into_data(), value.into_data(), expire)
+                    })
+                    .collect())
+            }
+            RedisData::HashListPackEx(_, hash) => Value::Hash(hash.fields),
+            RedisData::ListZipList(_, rs) => Value::List(ziplist_data(rs)?),
+            RedisData::ZSetZipList(_, rs) => Value::ZSet(to_zset(ziplist_data(rs)?)?),
+            RedisData::HashZipList(_, rs) => Value::Hash(to_hash(ziplist_data(rs)?)?),
+            RedisData::SetIntSet(_, rs) => {
+                let local_buf = rs.into_data();
+                let IntSet { ints, .. } = IntSet::from_buf(&local_buf)?;
+                Value::Set(ints.into_iter().map(|i| format!("{}", i).into_bytes()).collect())
+            }
+            RedisData::HashZipMap(_, zm) => {
+                Value::Hash(zm.entries
+                    .into_iter()
+                    .map(|ZipMapEntry { key, value, .. }| (key, value, None))
+                    .collect())
+            }
+            RedisData::ListQuickList(_, RedisList { items, .. }) => {
+                let mut datas = Vec::new();
+                for node in items {
+                    datas.extend(node.datas);
+                }
+                Value::List(datas)
+            }
+            RedisData::ListQuickList2(_, RedisList { items, .. }) => {
+                let mut datas = Vec::new();
+                for node in items {
+                    datas.extend(node.into_data());
+                }
+                Value::List(datas)
+            }
+            RedisData::HashListPack(_, lp) => Value::Hash(to_hash(lp.datas)?),
+            RedisData::ZSetListPack(_, lp) => Value::ZSet(to_zset(lp.datas)?),
+            RedisData::SetListPack(_, lp) => Value::Set(lp.datas),
+            RedisData::Stream(_, stream) => Value::Stream(stream),
+            RedisData::Module(_, module) => {
+                Value::Module {
+                    name: module.name,
+                    encver: module.encver,
+                    value: module.value,
+                }
+            }
+        };
+        Ok(value)
+    }
+}
+
+fn ziplist_data(rs: RedisString) -> Result<Vec<Vec<u8>>> {
+    let local_buf = rs.into_data();
+    let ZipList { entries, .. } = ZipList::from_buf(&local_buf)?;
+    Ok(entries.into_iter().map(|ZipListEntry { sp, .. }| sp.into_data()).collect())
+}
+
+// ziplist and listpack keep a hash as field, value, field, value...
+fn to_hash(datas: Vec<Vec<u8>>) -> Result<Vec<HashField>> {
+    faild!(datas.len() % 2 == 1, "hash field without a value");
+    let mut fields = Vec::new();
+    let mut iter = datas.into_iter();
+    while let (Some(field), Some(value)) = (iter.next(), iter.next()) {
+        fields.push((field, value, None));
+    }
+    Ok(fields)
+}
+
+// and a sorted set as member, score, member, score...
+fn to_zset(datas: Vec<Vec<u8>>) -> Result<Vec<(Vec<u8>, f64)>> {
+    faild!(datas.len() % 2 == 1, "sorted set member without a score");
+    let mut pairs = Vec::new();
+    let mut iter = datas.into_iter();
+    while let (Some(member), Some(score)) = (iter.next(), iter.next()) {
+        pairs.push((member, parse_score(&score)?));
+    }
+    Ok(pairs)
+}
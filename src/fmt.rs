@@ -9,11 +9,16 @@ pub enum RedisFmt {
 
 impl RedisFmt {
     fn is_crlf(&self) -> bool {
+        matches!(*self, RedisFmt::CRLF)
+    }
+    fn len(&self) -> usize {
         match self {
-            &RedisFmt::CRLF => true,
-            _ => false,
+            RedisFmt::Cmd(cmd) => cmd.len(),
+            RedisFmt::Raw(buf) => buf.len(),
+            RedisFmt::CRLF => 2,
         }
     }
+
     pub fn into_data(self) -> Vec<u8> {
         match self {
             RedisFmt::Cmd(cmd) => cmd.to_owned().into_bytes(),
@@ -38,6 +43,75 @@ impl RedisCmd {
         let RedisCmd(cmds) = self;
         cmds.into_iter().map(|x| x.into_data()).collect()
     }
+
+    /// split a command adding elements to a key into several commands within
+    /// `limits`, in the same order. a score and its member, or a field and
+    /// its value, always stay in the same command.
+    pub fn split(self, limits: &CmdLimits) -> Vec<RedisCmd> {
+        let width = match self.0.first() {
+            Some(&RedisFmt::Cmd(cmd)) => element_width(cmd),
+            _ => None,
+        };
+        let width = match width {
+            Some(width) if !limits.is_unlimited() && self.0.len() > 2 => width,
+            _ => return vec![self],
+        };
+        let RedisCmd(args) = self;
+        let mut args = args.into_iter();
+        let head: Vec<RedisFmt> = args.by_ref().take(2).collect();
+        let head_bytes = head.iter().map(|arg| arg.len()).sum::<usize>();
+
+        let mut cmds = Vec::new();
+        let mut current = head.clone();
+        let mut elements = 0;
+        let mut bytes = head_bytes;
+        loop {
+            let element: Vec<RedisFmt> = args.by_ref().take(width).collect();
+            if element.is_empty() {
+                break;
+            }
+            let element_bytes = element.iter().map(|arg| arg.len()).sum::<usize>();
+            if elements > 0 && limits.exceeded(elements + 1, bytes + element_bytes) {
+                cmds.push(RedisCmd(current));
+                current = head.clone();
+                elements = 0;
+                bytes = head_bytes;
+            }
+            current.extend(element);
+            elements += 1;
+            bytes += element_bytes;
+        }
+        cmds.push(RedisCmd(current));
+        cmds
+    }
+}
+
+/// arguments making one element of the commands which can be split.
+fn element_width(cmd: &str) -> Option<usize> {
+    match cmd {
+        "SADD" | "RPUSH" => Some(1),
+        "ZADD" | "HSET" => Some(2),
+        _ => None,
+    }
+}
+
+/// Bounds of a command adding elements to a key, 0 is no bound.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CmdLimits {
+    pub max_elements: usize,
+    /// bytes of all the arguments, the command name and key included.
+    pub max_bytes: usize,
+}
+
+impl CmdLimits {
+    fn is_unlimited(&self) -> bool {
+        self.max_elements == 0 && self.max_bytes == 0
+    }
+
+    fn exceeded(&self, elements: usize, bytes: usize) -> bool {
+        self.max_elements != 0 && elements > self.max_elements ||
+        self.max_bytes != 0 && bytes > self.max_bytes
+    }
 }
 
 pub trait Group {
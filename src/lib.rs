@@ -9,112 +9,570 @@ mod consts;
 mod codec;
 mod types;
 mod fmt;
+mod meta;
+mod module;
+mod version;
+mod entry;
+mod visitor;
+mod elements;
 
 pub use fmt::{RedisFmt, RedisCmd};
+pub use module::{ModuleTypeHandler, ModuleReader, ModuleValue, ModuleAux, JsonValue,
+                 RedisJsonHandler};
+pub use meta::{RdbMetadata, DatabaseStats, SlotInfo, KeyHint};
+pub use entry::{Entry, KeyEntry, Value};
+pub use visitor::RdbVisitor;
+pub use codec::{HashField, RedisStream, StreamId, StreamEntry, StreamGroup, StreamConsumer,
+                StreamNack};
 pub use com::{Result, Error};
 
-use fmt::{RedisFormat, Group};
+use fmt::{RedisFormat, Group, CmdLimits};
 use com::*;
 use codec::*;
 use types::*;
 use consts::*;
+use module::{ModuleRegistry, REJSON_MODULE_NAME};
+use version::{check_version, check_code, check_type};
+use visitor::visit_data;
+use elements::{Elements, is_collection, fmt_chunk, visit_start, visit_chunk, visit_end};
 
+use std::cmp::max;
+use std::collections::VecDeque;
 use std::io::{self, Read};
 use std::mem;
+use std::str;
+
+// bytes read from the source at a time
+const READ_CHUNK: usize = 64 * 1024;
 
 pub struct DefaultRdbParser {
+    // sliding window over the source, bytes before `cursor` are consumed
     local_buf: Vec<u8>,
     cursor: usize,
     parsed: Vec<RdbEntry>,
     state: State,
     end: Vec<u8>,
+    metadata: RdbMetadata,
+    databases: Vec<DatabaseStats>,
+    slots: Vec<SlotInfo>,
+    module_aux: Vec<ModuleAux>,
+    // database the current keys belong to
+    db: usize,
+    // database the emitted commands are running against
+    selected_db: usize,
+    // database the visitor was last told about
+    visited_db: Option<usize>,
+    // CRC64 of every byte consumed so far
+    checksum: u64,
+    restore_hints: bool,
+    collect_hints: bool,
+    hints: Vec<KeyHint>,
+    modules: ModuleRegistry,
+    // elements per chunk of a streamed collection, 0 decodes keys whole
+    element_chunk: usize,
+    // the collection being streamed
+    elements: Option<Elements>,
+    limits: CmdLimits,
 }
 
 impl Default for DefaultRdbParser {
     fn default() -> Self {
+        let mut modules = ModuleRegistry::default();
+        modules.register(REJSON_MODULE_NAME, Box::new(RedisJsonHandler));
         DefaultRdbParser {
             local_buf: Vec::new(),
             cursor: 0,
             parsed: Vec::new(),
             state: State::Header,
             end: Vec::new(),
+            metadata: RdbMetadata::default(),
+            databases: Vec::new(),
+            slots: Vec::new(),
+            module_aux: Vec::new(),
+            db: 0,
+            selected_db: 0,
+            visited_db: None,
+            checksum: 0,
+            restore_hints: false,
+            collect_hints: false,
+            hints: Vec::new(),
+            modules,
+            element_chunk: 0,
+            elements: None,
+            limits: CmdLimits::default(),
         }
     }
 }
 
 
 impl DefaultRdbParser {
+    /// parse `read` and return the commands of the entries completed so far.
+    /// when `read` would block before the end of the dump, call again with
+    /// more input until `is_finished`.
     pub fn read_to_cmd<R: Read>(&mut self, read: &mut R) -> Result<Vec<RedisCmd>> {
-        let _readed = self.read_to_local(read)?;
+        let mut cmds = Vec::new();
+        self.for_each_cmd(read, |cmd| cmds.push(cmd))?;
+        Ok(cmds)
+    }
+
+    /// parse `read` through a sliding window and hand over the commands of
+    /// the entries as soon as they are complete, so memory is bounded by the
+    /// largest value instead of the whole dump.
+    ///
+    /// returns once the dump is finished, or once `read` would block in the
+    /// middle of it; calling again with more input resumes where it stopped.
+    /// `read` ending before the end of the dump fails with `Error::More`.
+    pub fn for_each_cmd<R, F>(&mut self, read: &mut R, mut f: F) -> Result<()>
+        where R: Read,
+              F: FnMut(RedisCmd)
+    {
+        loop {
+            let parsed = self.parse();
+            for cmd in self.drain_cmds() {
+                f(cmd);
+            }
+            match parsed {
+                Err(Error::More) => {}
+                other => return other,
+            }
+            self.compact();
+            if self.read_to_local(read)? == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// push bytes of the dump, in any chunks, then `poll` the commands.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.compact();
+        self.local_buf.extend_from_slice(data);
+    }
+
+    /// the commands of the entries completed by the bytes fed so far, an
+    /// incomplete entry waits for the next `feed`.
+    pub fn poll(&mut self) -> Result<Vec<RedisCmd>> {
+        match self.parse() {
+            Ok(()) | Err(Error::More) => Ok(self.drain_cmds()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// iterate over the typed entries of `read`, parsed through the same
+    /// sliding window as `for_each_cmd`. when `read` ends before the end of
+    /// the dump, the last item is `Err(Error::More)`.
+    pub fn entries<'a, R: Read>(&'a mut self, read: &'a mut R) -> Entries<'a, R> {
+        Entries {
+            parser: self,
+            read,
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// drive `visitor` straight from the decoded values, without building
+    /// commands. like `for_each_cmd`, returns when `read` would block and
+    /// resumes on the next call, and fails with `Error::More` when `read` ends
+    /// early. `end_rdb` is called once the dump is finished.
+    pub fn visit<R, V>(&mut self, read: &mut R, visitor: &mut V) -> Result<()>
+        where R: Read,
+              V: RdbVisitor
+    {
+        if self.is_finished() {
+            return Ok(());
+        }
+        loop {
+            let parsed = self.parse();
+            let mut entries = vec![];
+            mem::swap(&mut entries, &mut self.parsed);
+            for entry in entries {
+                self.visit_entry(&entry, visitor)?;
+            }
+            match parsed {
+                Ok(()) => {
+                    if let Some(db) = self.visited_db.take() {
+                        visitor.end_database(db);
+                    }
+                    visitor.end_rdb();
+                    return Ok(());
+                }
+                Err(Error::More) => {}
+                Err(err) => return Err(err),
+            }
+            self.compact();
+            if self.read_to_local(read)? == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// the EOF opcode and the checksum have been parsed.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, State::End)
+    }
+
+    /// parse the entries in the window until the end of the dump, or until
+    /// one of them needs more bytes.
+    fn parse(&mut self) -> Result<()> {
         loop {
             match self.state {
                 State::Data => {
-                    let data = match self.data() {
+                    if self.element_chunk > 0 {
+                        match self.start_elements() {
+                            Err(Error::Other) => {}
+                            other => {
+                                let (entry, elements) = other?;
+                                self.advance(entry.shift());
+                                self.handle(entry);
+                                self.collect_hint(elements.db, &elements.key, &elements.eviction);
+                                self.elements = Some(elements);
+                                self.state = State::Elements;
+                                continue;
+                            }
+                        }
+                    }
+                    let entry = match self.entry() {
                         Err(Error::Other) => {
                             self.state = State::Crc;
                             continue;
                         }
                         other => other?,
                     };
-                    self.cursor += data.shift();
-                    self.parsed.push(data);
-                }
-                State::Sector => {
-                    let sector = match self.sector() {
-                        Err(Error::Other) => {
-                            self.state = State::Crc;
-                            continue;
-                        }
-                        otherwise => otherwise?,
-                    };
-                    self.cursor += sector.shift();
-                    self.state = State::Data;
+                    self.advance(entry.shift());
+                    self.handle(entry);
                 }
                 State::Header => {
                     let header = self.header()?;
-                    self.cursor += header.shift();
-                    self.state = State::Sector;
+                    self.advance(header.shift());
+                    self.handle(header);
+                    self.state = State::Data;
                 }
+                State::Elements => self.stream_elements()?,
                 State::Crc => {
-                    self.end = self.crc()?;
+                    let end = self.crc()?;
+                    // the EOF opcode is covered by the checksum, the footer is not
+                    self.advance(1);
+                    self.cursor += end.len();
+                    self.verify(&end)?;
+                    self.end = end;
                     self.state = State::End;
                 }
-                State::End => {
-                    break;
-                }
+                State::End => return Ok(()),
             };
         }
+    }
+
+    /// replay keys saved with an LRU idle time or LFU frequency as
+    /// `RESTORE ... IDLETIME/FREQ` so the target keeps them, off by default.
+    pub fn set_restore_hints(&mut self, enabled: bool) {
+        self.restore_hints = enabled;
+    }
+
+    /// keep the LRU idle time or LFU frequency of every key saved with one,
+    /// see `key_hints`. off by default.
+    pub fn set_collect_hints(&mut self, enabled: bool) {
+        self.collect_hints = enabled;
+    }
+
+    /// decode the values of the module type `name` with `handler`, values of
+    /// module types without a handler are replayed by RESTORE. `ReJSON-RL` is
+    /// decoded by `RedisJsonHandler` unless another handler is registered for
+    /// it.
+    pub fn register_module<H>(&mut self, name: &str, handler: H)
+        where H: ModuleTypeHandler + 'static
+    {
+        self.modules.register(name, Box::new(handler));
+    }
 
-        let entries = self.drain_buf();
+    /// hand the elements of lists, sets, sorted sets and hashes saved as
+    /// plain collections over `elements` at a time, as one command or one
+    /// `Entry::Chunk` per chunk, so a huge key is never held whole. keys saved
+    /// as a ziplist, listpack or intset are a single blob bounded by the
+    /// server config and are still decoded whole. 0, the default, turns it
+    /// off.
+    pub fn set_element_chunk(&mut self, elements: usize) {
+        self.element_chunk = elements;
+    }
+
+    /// split the SADD, HSET, ZADD and RPUSH commands of big keys so
+    /// that none carries more than `elements` members, fields or score and
+    /// member pairs. 0, the default, is no limit.
+    pub fn set_max_elements_per_cmd(&mut self, elements: usize) {
+        self.limits.max_elements = elements;
+    }
+
+    /// split the same commands so that their arguments take at most `bytes`
+    /// bytes, e.g. to stay under the proto-max-bulk-len of the target. an
+    /// element larger than that still gets a command of its own. 0, the
+    /// default, is no limit.
+    pub fn set_max_bytes_per_cmd(&mut self, bytes: usize) {
+        self.limits.max_bytes = bytes;
+    }
+
+    /// version and AUX fields of the rdb parsed so far.
+    pub fn metadata(&self) -> &RdbMetadata {
+        &self.metadata
+    }
+
+    /// declared and parsed key counts of every database met so far.
+    pub fn databases(&self) -> &[DatabaseStats] {
+        &self.databases
+    }
+
+    /// key counts of the cluster slots met so far.
+    pub fn slots(&self) -> &[SlotInfo] {
+        &self.slots
+    }
+
+    /// idle times and frequencies of the keys parsed so far, in order.
+    pub fn key_hints(&self) -> &[KeyHint] {
+        &self.hints
+    }
+
+    /// module global data met so far.
+    pub fn module_aux(&self) -> &[ModuleAux] {
+        &self.module_aux
+    }
+
+    /// the head of a plain collection, its items are decoded by
+    /// `stream_elements`.
+    fn start_elements(&self) -> Result<(RdbEntry, Elements)> {
+        let version = self.version();
+        let src = self.local_buf();
+        more!(src.is_empty());
+        other!(src[0] == REDIS_RDB_OPCODE_EOF);
+        check_code(version, src[0])?;
+        let expire = ExpireTime::from_buf(src)?;
+        let mut pos = expire.shift();
+        more!(src.len() <= pos);
+        let eviction = Eviction::from_buf(&src[pos..])?;
+        pos += eviction.shift();
+        more!(src.len() <= pos);
+        let rtype = src[pos];
+        other!(!is_collection(rtype));
+        // RESTORE needs the whole payload
+        other!(self.restore_hints && !eviction.is_none());
+        check_type(version, rtype)?;
+        let key = RedisString::from_buf(&src[pos + 1..])?;
+        pos += 1 + key.shift();
+        more!(src.len() <= pos);
+        let length = Length::from_buf(&src[pos..])?;
+        pos += length.shift();
+        let elements = Elements::new(self.db,
+                                     key.clone(),
+                                     rtype,
+                                     expire,
+                                     eviction,
+                                     length.length());
+        let entry = RdbEntry::KeyStart {
+            key,
+            rtype,
+            length: length.length(),
+            expire,
+            size: pos,
+        };
+        Ok((entry, elements))
+    }
+
+    /// decode the items of the streamed collection one by one, a chunk is
+    /// handed over once full.
+    fn stream_elements(&mut self) -> Result<()> {
+        loop {
+            let (remaining, chunk_len) = {
+                let elements = self.elements.as_ref().expect("streaming a collection");
+                (elements.remaining, elements.chunk_len())
+            };
+            if chunk_len >= self.element_chunk || remaining == 0 && chunk_len > 0 {
+                let elements = self.elements.as_mut().expect("streaming a collection");
+                self.parsed.push(RdbEntry::Elements {
+                    db: elements.db,
+                    key: elements.key.clone(),
+                    expire: elements.expire,
+                    eviction: elements.eviction.clone(),
+                    value: elements.take_chunk(),
+                });
+            }
+            if remaining == 0 {
+                let elements = self.elements.take().expect("streaming a collection");
+                self.parsed.push(RdbEntry::KeyEnd {
+                    key: elements.key,
+                    rtype: elements.rtype,
+                    expire: elements.expire,
+                    emitted: elements.emitted,
+                });
+                self.state = State::Data;
+                return Ok(());
+            }
+            let shift = {
+                let src = &self.local_buf[self.cursor..];
+                more!(src.is_empty());
+                self.elements.as_mut().expect("streaming a collection").push(src)?
+            };
+            self.advance(shift);
+        }
+    }
+
+    fn advance(&mut self, shift: usize) {
+        let consumed = &self.local_buf[self.cursor..self.cursor + shift];
+        self.checksum = crc64(self.checksum, consumed);
+        self.cursor += shift;
+    }
+
+    /// an all zero footer means the checksum was disabled when saving.
+    fn verify(&self, end: &[u8]) -> Result<()> {
+        if end.len() < REDIS_RDB_CHECKSUM_LEN {
+            return Ok(());
+        }
+        let expected = buf_to_u64(end);
+        if expected != 0 && expected != self.checksum {
+            return Err(Error::Checksum {
+                expected,
+                computed: self.checksum,
+            });
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, entry: RdbEntry) {
+        match &entry {
+            RdbEntry::Version(version) => {
+                self.metadata.version = Some(*version);
+            }
+            RdbEntry::Aux { key, value } => {
+                self.metadata.insert(key.clone().into_data(), value.clone().into_data());
+            }
+            RdbEntry::Sector(db) => {
+                self.db = db.length();
+                self.databases.push(DatabaseStats::new(self.db));
+            }
+            RdbEntry::ResizeDb { db_size, expires_size } => {
+                if let Some(stats) = self.databases.last_mut() {
+                    stats.declared_keys = Some(db_size.length());
+                    stats.declared_expires = Some(expires_size.length());
+                }
+            }
+            RdbEntry::SlotInfo { slot, size, expires_size } => {
+                self.slots.push(SlotInfo {
+                    slot: slot.length(),
+                    size: size.length(),
+                    expires_size: expires_size.length(),
+                });
+            }
+            RdbEntry::ModuleAux(aux) => {
+                self.module_aux.push(aux.clone());
+            }
+            RdbEntry::Function { .. } |
+            RdbEntry::Elements { .. } |
+            RdbEntry::KeyEnd { .. } => {}
+            RdbEntry::Data { expire, .. } |
+            RdbEntry::KeyStart { expire, .. } => {
+                if let Some(stats) = self.databases.last_mut() {
+                    stats.keys += 1;
+                    if !expire.is_none() {
+                        stats.expires += 1;
+                    }
+                }
+            }
+        }
+        if let &RdbEntry::Data { db, ref eviction, ref data, .. } = &entry {
+            self.collect_hint(db, data.key(), eviction);
+        }
+        self.parsed.push(entry);
+    }
+
+    fn collect_hint(&mut self, db: usize, key: &Key, eviction: &Eviction) {
+        if self.collect_hints && !eviction.is_none() {
+            self.hints.push(eviction.to_hint(db, key.clone().into_data()));
+        }
+    }
+
+    fn visit_entry<V: RdbVisitor>(&mut self, entry: &RdbEntry, visitor: &mut V) -> Result<()> {
+        match entry {
+            RdbEntry::Version(version) => visitor.start_rdb(*version),
+            RdbEntry::Aux { key, value } => {
+                visitor.aux_field(&key.as_bytes(), &value.as_bytes());
+            }
+            RdbEntry::Sector(db) => {
+                if let Some(db) = self.visited_db.take() {
+                    visitor.end_database(db);
+                }
+                self.visited_db = Some(db.length());
+                visitor.start_database(db.length());
+            }
+            RdbEntry::ResizeDb { db_size, expires_size } => {
+                visitor.resize_db(db_size.length(), expires_size.length());
+            }
+            RdbEntry::Function { library, .. } => visitor.function(library),
+            RdbEntry::Data { expire, data, .. } => visit_data(data, *expire, visitor)?,
+            RdbEntry::KeyStart { key, rtype, length, expire, .. } => {
+                visit_start(&key.as_bytes(), *rtype, *length, *expire, visitor);
+            }
+            RdbEntry::Elements { key, value, .. } => {
+                visit_chunk(&key.as_bytes(), value, visitor);
+            }
+            RdbEntry::KeyEnd { key, rtype, .. } => visit_end(&key.as_bytes(), *rtype, visitor),
+            // kept by `slots` and `module_aux`
+            RdbEntry::SlotInfo { .. } |
+            RdbEntry::ModuleAux(_) => {}
+        }
+        Ok(())
+    }
+
+    fn drain_entries(&mut self) -> Vec<Result<Entry>> {
+        let mut entries = vec![];
+        mem::swap(&mut entries, &mut self.parsed);
+        entries.into_iter().filter_map(|entry| entry.into_entry()).collect()
+    }
+
+    fn drain_cmds(&mut self) -> Vec<RedisCmd> {
+        let mut entries = vec![];
+        mem::swap(&mut entries, &mut self.parsed);
         let mut fmts = vec![];
         for entry in entries {
+            match &entry {
+                &RdbEntry::Data { db, .. } |
+                &RdbEntry::Elements { db, .. } if db != self.selected_db => {
+                    fmt_select(db, &mut fmts);
+                    self.selected_db = db;
+                }
+                _ => {}
+            }
             entry.fmt(&mut fmts);
         }
-        let groups = Group::group(fmts);
-        Ok(groups)
+        let limits = self.limits;
+        Group::group(fmts).into_iter().flat_map(|cmd| cmd.split(&limits)).collect()
     }
 
-    fn drain_buf(&mut self) -> Vec<RdbEntry> {
-        let mut entries = vec![];
-        mem::swap(&mut entries, &mut self.parsed);
+    /// drop the consumed bytes, the window keeps the unparsed tail only.
+    fn compact(&mut self) {
+        self.local_buf.drain(..self.cursor);
         self.cursor = 0;
-        entries
     }
 }
 
 impl RdbParser for DefaultRdbParser {
+    /// read a chunk of at least `READ_CHUNK` bytes, and at least as many
+    /// bytes as the window holds so a value larger than a chunk is parsed
+    /// again only a logarithmic number of times.
+    ///
+    /// `Ok(0)` means `read` would block, `read` ending without a byte fails
+    /// with `Error::More`.
     fn read_to_local<R: Read>(&mut self, read: &mut R) -> Result<usize> {
         let start_len = self.local_buf.len();
+        let want = start_len + max(READ_CHUNK, start_len - self.cursor);
         let mut len = start_len;
-        let mut new_write_size = 16;
+        self.local_buf.resize(want, 0);
         let ret;
         loop {
-            if len == self.local_buf.len() {
-                new_write_size *= 2;
-                self.local_buf.resize(len + new_write_size, 0);
+            if len == want {
+                ret = Ok(len - start_len);
+                break;
             }
 
             match read.read(&mut self.local_buf[len..]) {
+                Ok(0) if len == start_len => {
+                    ret = Err(Error::More);
+                    break;
+                }
                 Ok(0) => {
                     ret = Ok(len - start_len);
                     break;
@@ -139,57 +597,271 @@ impl RdbParser for DefaultRdbParser {
     fn local_buf(&self) -> &[u8] {
         &self.local_buf[min(self.cursor, self.local_buf.len())..]
     }
+
+    fn db(&self) -> usize {
+        self.db
+    }
+
+    fn version(&self) -> u32 {
+        self.metadata.version.unwrap_or(0)
+    }
+
+    fn restore_hints(&self) -> bool {
+        self.restore_hints
+    }
+
+    fn modules(&self) -> &ModuleRegistry {
+        &self.modules
+    }
 }
 
 
 trait RdbParser {
     fn read_to_local<R: Read>(&mut self, read: &mut R) -> Result<usize>;
     fn local_buf(&self) -> &[u8];
+    fn db(&self) -> usize;
+    fn version(&self) -> u32;
+    fn restore_hints(&self) -> bool;
+    fn modules(&self) -> &ModuleRegistry;
 
     fn crc(&mut self) -> Result<Vec<u8>> {
+        let version = self.version();
         let src = self.local_buf();
-        other!(src[0] != 0xff);
-        Ok(src[1..].to_vec())
+        more!(src.is_empty());
+        other!(src[0] != REDIS_RDB_OPCODE_EOF);
+        // nothing follows the EOF opcode before the checksum was added
+        if version < REDIS_RDB_CHECKSUM_VERSION {
+            return Ok(Vec::new());
+        }
+        more!(src.len() < 1 + REDIS_RDB_CHECKSUM_LEN);
+        Ok(src[1..1 + REDIS_RDB_CHECKSUM_LEN].to_vec())
     }
 
     fn header(&mut self) -> Result<RdbEntry> {
         let src = self.local_buf();
         more!(src.len() < REDIS_MAGIC_STRING.len() + 4);
+        faild!(!src.starts_with(REDIS_MAGIC_STRING.as_bytes()),
+               "not a rdb file, magic string REDIS not found");
         let version = &src[REDIS_MAGIC_STRING.len()..REDIS_MAGIC_STRING.len() + 4];
-        let version_str = String::from_utf8_lossy(version);
-        let version_u32 = version_str.parse::<u32>().unwrap();
+        let version_u32 = str::from_utf8(version)
+            .ok()
+            .and_then(|version| version.parse::<u32>().ok())
+            .ok_or(Error::Faild("rdb version is not a number"))?;
+        check_version(version_u32)?;
         Ok(RdbEntry::Version(version_u32))
     }
 
+    /// any entry between the header and the EOF opcode, opcodes first.
+    fn entry(&mut self) -> Result<RdbEntry> {
+        let version = self.version();
+        {
+            let src = self.local_buf();
+            more!(src.is_empty());
+            check_code(version, src[0])?;
+        }
+        choice!(self.sector());
+        choice!(self.resize_db());
+        choice!(self.aux());
+        choice!(self.function());
+        choice!(self.module_aux());
+        choice!(self.slot_info());
+        self.data()
+    }
+
+    fn aux(&mut self) -> Result<RdbEntry> {
+        let src = self.local_buf();
+        more!(src.is_empty());
+        other!(src[0] != REDIS_RDB_OPCODE_AUX);
+        let key = RedisString::from_buf(&src[1..])?;
+        let value = RedisString::from_buf(&src[1 + key.shift()..])?;
+        Ok(RdbEntry::Aux {
+            key,
+            value,
+        })
+    }
+
     fn sector(&mut self) -> Result<RdbEntry> {
         let src = self.local_buf();
-        more!(src.len() < 2);
+        more!(src.is_empty());
         other!(src[0] != REDIS_RDB_OPCODE_SELECTDB);
         let length = Length::from_buf(&src[1..])?;
         Ok(RdbEntry::Sector(length))
     }
 
+    fn resize_db(&mut self) -> Result<RdbEntry> {
+        let src = self.local_buf();
+        more!(src.is_empty());
+        other!(src[0] != REDIS_RDB_OPCODE_RESIZEDB);
+        let db_size = Length::from_buf(&src[1..])?;
+        let expires_size = Length::from_buf(&src[1 + db_size.shift()..])?;
+        Ok(RdbEntry::ResizeDb {
+            db_size,
+            expires_size,
+        })
+    }
+
+    fn module_aux(&mut self) -> Result<RdbEntry> {
+        let modules = self.modules();
+        let src = self.local_buf();
+        more!(src.is_empty());
+        other!(src[0] != REDIS_RDB_OPCODE_MODULE_AUX);
+        let aux = ModuleAux::from_buf(&src[1..], modules)?;
+        Ok(RdbEntry::ModuleAux(aux))
+    }
+
+    fn slot_info(&mut self) -> Result<RdbEntry> {
+        let src = self.local_buf();
+        more!(src.is_empty());
+        other!(src[0] != REDIS_RDB_OPCODE_SLOT_INFO);
+        let slot = Length::from_buf(&src[1..])?;
+        let pos = 1 + slot.shift();
+        more!(src.len() <= pos);
+        let size = Length::from_buf(&src[pos..])?;
+        let pos = pos + size.shift();
+        more!(src.len() <= pos);
+        let expires_size = Length::from_buf(&src[pos..])?;
+        Ok(RdbEntry::SlotInfo {
+            slot,
+            size,
+            expires_size,
+        })
+    }
+
+    /// FUNCTION2 saves the source of a library, the redis 7.0 release
+    /// candidates saved name, engine, description and code apart.
+    fn function(&mut self) -> Result<RdbEntry> {
+        let src = self.local_buf();
+        more!(src.is_empty());
+        match src[0] {
+            REDIS_RDB_OPCODE_FUNCTION2 => {
+                let library = RedisString::from_buf(&src[1..])?;
+                Ok(RdbEntry::Function {
+                    size: 1 + library.shift(),
+                    library: library.into_data(),
+                })
+            }
+            REDIS_RDB_OPCODE_FUNCTION_PRE_GA => {
+                let mut pos = 1;
+                let name = RedisString::from_buf(&src[pos..])?;
+                pos += name.shift();
+                let engine = RedisString::from_buf(&src[pos..])?;
+                pos += engine.shift();
+                more!(src.len() <= pos);
+                let has_desc = Length::from_buf(&src[pos..])?;
+                pos += has_desc.shift();
+                if has_desc.value() != 0 {
+                    let desc = RedisString::from_buf(&src[pos..])?;
+                    pos += desc.shift();
+                }
+                let code = RedisString::from_buf(&src[pos..])?;
+                pos += code.shift();
+                // the shebang FUNCTION LOAD expects since 7.0 GA
+                let engine = String::from_utf8_lossy(&engine.into_data()).to_lowercase();
+                let mut library = format!("#!{} name=", engine).into_bytes();
+                library.extend(name.into_data());
+                library.push(b'\n');
+                library.extend(code.into_data());
+                Ok(RdbEntry::Function {
+                    library,
+                    size: pos,
+                })
+            }
+            _ => Err(Error::Other),
+        }
+    }
+
     fn data(&mut self) -> Result<RdbEntry> {
+        let db = self.db();
+        let version = self.version();
+        let restore_hints = self.restore_hints();
+        let modules = self.modules();
         let src = self.local_buf();
+        more!(src.is_empty());
         // meet EOF
-        if src[0] == 0xff {
+        if src[0] == REDIS_RDB_OPCODE_EOF {
             return Err(Error::Other);
         }
         let expire = ExpireTime::from_buf(src)?;
-        let data = RedisData::from_buf(&src[expire.shift()..])?;
+        let src = &src[expire.shift()..];
+        more!(src.is_empty());
+        check_code(version, src[0])?;
+        let eviction = Eviction::from_buf(src)?;
+        let src = &src[eviction.shift()..];
+        more!(src.is_empty());
+        check_type(version, src[0])?;
+        let data = RedisData::from_buf_with(src, modules)?;
+        let restore = if data.needs_restore() || restore_hints && !eviction.is_none() {
+            let value_start = 1 + data.copy_key().shift();
+            Some(dump_payload(src[0], &src[value_start..data.shift()], version))
+        } else {
+            None
+        };
         Ok(RdbEntry::Data {
-            expire: expire,
-            data: data,
+            db,
+            expire,
+            eviction,
+            data,
+            restore,
         })
     }
 }
 
 
+/// Iterator over the typed entries of a rdb, see `DefaultRdbParser::entries`.
+pub struct Entries<'a, R: 'a> {
+    parser: &'a mut DefaultRdbParser,
+    read: &'a mut R,
+    pending: VecDeque<Result<Entry>>,
+    done: bool,
+}
+
+impl<'a, R: Read> Entries<'a, R> {
+    /// parse the window, or read more of it when no entry is complete.
+    fn step(&mut self) {
+        let parsed = self.parser.parse();
+        self.pending.extend(self.parser.drain_entries());
+        match parsed {
+            Ok(()) => self.done = true,
+            Err(Error::More) => {
+                self.parser.compact();
+                match self.parser.read_to_local(self.read) {
+                    // would block
+                    Ok(0) => {
+                        self.done = true;
+                        self.pending.push_back(Err(Error::More));
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        self.done = true;
+                        self.pending.push_back(Err(err));
+                    }
+                }
+            }
+            Err(err) => {
+                self.done = true;
+                self.pending.push_back(Err(err));
+            }
+        }
+    }
+}
+
+impl<'a, R: Read> Iterator for Entries<'a, R> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Result<Entry>> {
+        while self.pending.is_empty() && !self.done {
+            self.step();
+        }
+        self.pending.pop_front()
+    }
+}
+
 #[derive(Debug)]
 enum State {
     Header,
-    Sector,
     Data,
+    // items of a collection streamed element by element
+    Elements,
     Crc,
     End,
 }
@@ -197,8 +869,43 @@ enum State {
 #[derive(Debug)]
 enum RdbEntry {
     Version(u32),
+    Aux { key: RedisString, value: RedisString },
     Sector(Length),
-    Data { expire: ExpireTime, data: RedisData },
+    ResizeDb { db_size: Length, expires_size: Length },
+    ModuleAux(ModuleAux),
+    SlotInfo { slot: Length, size: Length, expires_size: Length },
+    // saved before the databases, so it is loaded before any key
+    Function { library: Vec<u8>, size: usize },
+    Data {
+        db: usize,
+        expire: ExpireTime,
+        eviction: Eviction,
+        data: RedisData,
+        // DUMP payload of the value when it is replayed by RESTORE
+        restore: Option<Vec<u8>>,
+    },
+    // a collection streamed element by element: its head, its chunks, then
+    // its end once every item is decoded
+    KeyStart {
+        key: Key,
+        rtype: u8,
+        length: usize,
+        expire: ExpireTime,
+        size: usize,
+    },
+    Elements {
+        db: usize,
+        key: Key,
+        expire: ExpireTime,
+        eviction: Eviction,
+        value: Value,
+    },
+    KeyEnd {
+        key: Key,
+        rtype: u8,
+        expire: ExpireTime,
+        emitted: bool,
+    },
 }
 
 impl Shift for RdbEntry {
@@ -206,24 +913,110 @@ impl Shift for RdbEntry {
     fn shift(&self) -> usize {
         match self {
             // len('REDIS') + version_number
-            &RdbEntry::Version(_) => 5 + 4,
-            // 0xFE + u8
-            &RdbEntry::Sector(_) => 2,
-            &RdbEntry::Data { ref expire, ref data } => expire.shift() + data.shift(),
+            RdbEntry::Version(_) => 5 + 4,
+            // 0xFA + key + value
+            RdbEntry::Aux { key, value } => 1 + key.shift() + value.shift(),
+            // 0xFE + db number
+            RdbEntry::Sector(db) => 1 + db.shift(),
+            // 0xFB + db size + expires size
+            RdbEntry::ResizeDb { db_size, expires_size } => {
+                1 + db_size.shift() + expires_size.shift()
+            }
+            // 0xF7 + module id + when + values
+            RdbEntry::ModuleAux(aux) => 1 + aux.shift(),
+            // 0xF4 + slot id + slot size + expires slot size
+            RdbEntry::SlotInfo { slot, size, expires_size } => {
+                1 + slot.shift() + size.shift() + expires_size.shift()
+            }
+            RdbEntry::Function { size, .. } => *size,
+            RdbEntry::Data { expire, eviction, data, .. } => {
+                expire.shift() + eviction.shift() + data.shift()
+            }
+            RdbEntry::KeyStart { size, .. } => *size,
+            // the items are consumed one by one while they are decoded
+            RdbEntry::Elements { .. } |
+            RdbEntry::KeyEnd { .. } => 0,
         }
     }
 }
 
+impl RdbEntry {
+    /// `None` for the head and end of a streamed collection.
+    fn into_entry(self) -> Option<Result<Entry>> {
+        let entry = match self {
+            RdbEntry::Version(version) => Entry::Version(version),
+            RdbEntry::Aux { key, value } => {
+                Entry::Aux {
+                    key: key.into_data(),
+                    value: value.into_data(),
+                }
+            }
+            RdbEntry::Sector(db) => Entry::SelectDb(db.length()),
+            RdbEntry::ResizeDb { db_size, expires_size } => {
+                Entry::ResizeDb {
+                    db_size: db_size.length(),
+                    expires_size: expires_size.length(),
+                }
+            }
+            RdbEntry::ModuleAux(aux) => Entry::ModuleAux(aux),
+            RdbEntry::SlotInfo { slot, size, expires_size } => {
+                Entry::SlotInfo(SlotInfo {
+                    slot: slot.length(),
+                    size: size.length(),
+                    expires_size: expires_size.length(),
+                })
+            }
+            RdbEntry::Function { library, .. } => Entry::Function(library),
+            RdbEntry::Data { db, expire, eviction, data, .. } => {
+                match KeyEntry::new(db, expire, eviction, data) {
+                    Ok(key) => Entry::Key(key),
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+            RdbEntry::Elements { db, key, expire, eviction, value, .. } => {
+                Entry::Chunk(KeyEntry::with_value(db, key.into_data(), expire, eviction, value))
+            }
+            RdbEntry::KeyStart { .. } |
+            RdbEntry::KeyEnd { .. } => return None,
+        };
+        Some(Ok(entry))
+    }
+}
+
 impl RedisFormat for RdbEntry {
     fn fmt(self, buf: &mut Vec<RedisFmt>) -> usize {
         match self {
-            RdbEntry::Data { expire, data } => {
+            RdbEntry::Function { library, .. } => {
+                buf.push(RedisFmt::Cmd("FUNCTION"));
+                buf.push(RedisFmt::Cmd("LOAD"));
+                buf.push(RedisFmt::Cmd("REPLACE"));
+                buf.push(RedisFmt::Raw(library));
+                buf.push(RedisFmt::CRLF);
+                1
+            }
+            RdbEntry::Data { expire, eviction, data, restore: Some(payload), .. } => {
+                fmt_restore(data.copy_key(), payload, expire, eviction, buf)
+            }
+            RdbEntry::Data { expire, data, .. } => {
                 let key = data.copy_key();
-                let mut count = data.fmt(buf);
-                count += expire.fmt(key, buf);
-                count
+                let count = data.fmt(buf);
+                // a module handler may rebuild a key with no command
+                if count == 0 {
+                    return 0;
+                }
+                count + expire.fmt(key, buf)
             }
+            RdbEntry::Elements { key, value, .. } => fmt_chunk(key.into_data(), value, buf),
+            // no key to expire when the collection had no element
+            RdbEntry::KeyEnd { key, expire, emitted: true, .. } => expire.fmt(key, buf),
             _ => 0,
         }
     }
 }
+
+fn fmt_select(db: usize, buf: &mut Vec<RedisFmt>) -> usize {
+    buf.push(RedisFmt::Cmd("SELECT"));
+    buf.push(RedisFmt::Raw(format!("{}", db).into_bytes()));
+    buf.push(RedisFmt::CRLF);
+    1
+}
@@ -0,0 +1,108 @@
+use std::str;
+
+/// Header information of a rdb file, collected from the version number and
+/// the AUX (0xFA) fields that redis 4.0+ writes before the first database.
+#[derive(Debug, Clone, Default)]
+pub struct RdbMetadata {
+    /// rdb format version read from `REDISxxxx`.
+    pub version: Option<u32>,
+    /// `redis-ver`, the version of the redis server which produced the dump.
+    pub redis_ver: Option<String>,
+    /// `redis-bits`, 32 or 64.
+    pub redis_bits: Option<u32>,
+    /// `ctime`, unix timestamp in seconds when the dump was created.
+    pub ctime: Option<u64>,
+    /// `used-mem`, memory used by the server when the dump was created.
+    pub used_mem: Option<u64>,
+    /// `repl-id`, the replication id of the server.
+    pub repl_id: Option<String>,
+    /// `repl-offset`, the replication offset of the server.
+    pub repl_offset: Option<i64>,
+    /// `aof-base`, whether the rdb is the preamble of an AOF file.
+    pub aof_base: Option<bool>,
+    /// AUX fields newbee does not know about, in the order they appear.
+    pub others: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl RdbMetadata {
+    /// record one AUX field, unknown or malformed fields are kept in `others`.
+    pub fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        let known = match &key[..] {
+            b"redis-ver" => to_string(&value).map(|v| self.redis_ver = Some(v)),
+            b"redis-bits" => parse(&value).map(|v| self.redis_bits = Some(v)),
+            b"ctime" => parse(&value).map(|v| self.ctime = Some(v)),
+            b"used-mem" => parse(&value).map(|v| self.used_mem = Some(v)),
+            b"repl-id" => to_string(&value).map(|v| self.repl_id = Some(v)),
+            b"repl-offset" => parse(&value).map(|v| self.repl_offset = Some(v)),
+            b"aof-base" => parse::<u8>(&value).map(|v| self.aof_base = Some(v != 0)),
+            _ => None,
+        };
+        if known.is_none() {
+            self.others.push((key, value));
+        }
+    }
+
+    /// get the raw value of an AUX field which is not exposed as a typed field.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.others.iter().find(|&(k, _)| &k[..] == key).map(|(_, v)| &v[..])
+    }
+}
+
+fn to_string(value: &[u8]) -> Option<String> {
+    str::from_utf8(value).ok().map(|v| v.to_owned())
+}
+
+fn parse<T: str::FromStr>(value: &[u8]) -> Option<T> {
+    str::from_utf8(value).ok().and_then(|v| v.parse().ok())
+}
+
+/// Key counts of one database: the hint written by RESIZEDB (0xFB) and the
+/// number of keys the parser actually met.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatabaseStats {
+    /// database number given by SELECTDB.
+    pub db: usize,
+    /// db size declared by RESIZEDB, `None` for dumps without the opcode.
+    pub declared_keys: Option<usize>,
+    /// expires size declared by RESIZEDB.
+    pub declared_expires: Option<usize>,
+    /// keys parsed in this database.
+    pub keys: usize,
+    /// keys with an expire time parsed in this database.
+    pub expires: usize,
+}
+
+impl DatabaseStats {
+    pub fn new(db: usize) -> DatabaseStats {
+        DatabaseStats { db, ..Default::default() }
+    }
+
+    /// false when RESIZEDB declared more keys than were parsed, which means
+    /// the dump is truncated or the parse stopped early.
+    pub fn is_complete(&self) -> bool {
+        self.keys >= self.declared_keys.unwrap_or(0) &&
+        self.expires >= self.declared_expires.unwrap_or(0)
+    }
+}
+
+/// Key counts of one hash slot, saved by SLOT_INFO (0xF4) in cluster mode.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SlotInfo {
+    pub slot: usize,
+    /// keys in the slot.
+    pub size: usize,
+    /// keys with an expire time in the slot.
+    pub expires_size: usize,
+}
+
+/// LRU idle time or LFU frequency saved in front of a key, collected when
+/// `DefaultRdbParser::set_collect_hints` is on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyHint {
+    pub db: usize,
+    pub key: Vec<u8>,
+    /// seconds since the last access, under an LRU maxmemory-policy.
+    pub idle: Option<u64>,
+    /// logarithmic access counter, under an LFU maxmemory-policy.
+    pub freq: Option<u8>,
+}
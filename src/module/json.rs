@@ -0,0 +1,320 @@
+use com::*;
+use super::{ModuleTypeHandler, ModuleReader, ModuleValue};
+use std::char;
+use std::str;
+
+pub const REJSON_MODULE_NAME: &str = "ReJSON-RL";
+
+// node types of the RedisJSON 1.x tree, encoding version 0
+const REJSON_NODE_NULL: u64 = 0x01;
+const REJSON_NODE_STRING: u64 = 0x02;
+const REJSON_NODE_NUMBER: u64 = 0x04;
+const REJSON_NODE_INTEGER: u64 = 0x08;
+const REJSON_NODE_BOOLEAN: u64 = 0x10;
+const REJSON_NODE_DICT: u64 = 0x20;
+const REJSON_NODE_ARRAY: u64 = 0x40;
+const REJSON_NODE_KEYVAL: u64 = 0x80;
+
+/// A JSON document, object members keep their order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// parse a JSON text, as saved by RedisJSON since encoding version 2.
+    pub fn parse(src: &[u8]) -> Result<JsonValue> {
+        let mut parser = JsonParser { src, pos: 0 };
+        let value = parser.value()?;
+        parser.whitespace();
+        faild!(parser.pos != src.len(), "trailing bytes after json value");
+        Ok(value)
+    }
+
+    /// serialize into compact JSON text.
+    pub fn to_json(&self) -> String {
+        let mut buf = String::new();
+        self.write(&mut buf);
+        buf
+    }
+
+    fn write(&self, buf: &mut String) {
+        match self {
+            JsonValue::Null => buf.push_str("null"),
+            JsonValue::Bool(b) => buf.push_str(if *b { "true" } else { "false" }),
+            JsonValue::Int(i) => buf.push_str(&format!("{}", i)),
+            JsonValue::Number(n) => write_number(*n, buf),
+            JsonValue::String(s) => write_string(s, buf),
+            JsonValue::Array(items) => {
+                buf.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        buf.push(',');
+                    }
+                    item.write(buf);
+                }
+                buf.push(']');
+            }
+            JsonValue::Object(members) => {
+                buf.push('{');
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i != 0 {
+                        buf.push(',');
+                    }
+                    write_string(key, buf);
+                    buf.push(':');
+                    value.write(buf);
+                }
+                buf.push('}');
+            }
+        }
+    }
+}
+
+// JSON has no inf or nan, and a float keeps its fraction to stay a float
+fn write_number(n: f64, buf: &mut String) {
+    if !n.is_finite() {
+        buf.push_str("null");
+        return;
+    }
+    let digits = fmt_double(n);
+    buf.push_str(&digits);
+    if !digits.contains(['.', 'e']) {
+        buf.push_str(".0");
+    }
+}
+
+fn write_string(s: &str, buf: &mut String) {
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            '\u{8}' => buf.push_str("\\b"),
+            '\u{c}' => buf.push_str("\\f"),
+            c if (c as u32) < 0x20 => buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+struct JsonParser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn whitespace(&mut self) {
+        while self.pos < self.src.len() && b" \t\r\n".contains(&self.src[self.pos]) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Result<u8> {
+        self.whitespace();
+        faild!(self.pos >= self.src.len(), "json value ended too early");
+        Ok(self.src[self.pos])
+    }
+
+    fn literal(&mut self, word: &[u8], value: JsonValue) -> Result<JsonValue> {
+        faild!(!self.src[self.pos..].starts_with(word), "invalid json literal");
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<JsonValue> {
+        match self.peek()? {
+            b'n' => self.literal(b"null", JsonValue::Null),
+            b't' => self.literal(b"true", JsonValue::Bool(true)),
+            b'f' => self.literal(b"false", JsonValue::Bool(false)),
+            b'"' => Ok(JsonValue::String(self.string()?)),
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.peek()? == b']' {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    match self.peek()? {
+                        b',' => self.pos += 1,
+                        b']' => {
+                            self.pos += 1;
+                            return Ok(JsonValue::Array(items));
+                        }
+                        _ => return Err(Error::Faild("invalid json array")),
+                    }
+                }
+            }
+            b'{' => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if self.peek()? == b'}' {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(members));
+                }
+                loop {
+                    faild!(self.peek()? != b'"', "invalid json object key");
+                    let key = self.string()?;
+                    faild!(self.peek()? != b':', "invalid json object");
+                    self.pos += 1;
+                    members.push((key, self.value()?));
+                    match self.peek()? {
+                        b',' => self.pos += 1,
+                        b'}' => {
+                            self.pos += 1;
+                            return Ok(JsonValue::Object(members));
+                        }
+                        _ => return Err(Error::Faild("invalid json object")),
+                    }
+                }
+            }
+            _ => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Result<JsonValue> {
+        let start = self.pos;
+        let mut float = false;
+        while self.pos < self.src.len() {
+            match self.src[self.pos] {
+                b'0'..=b'9' | b'-' | b'+' => {}
+                b'.' | b'e' | b'E' => float = true,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        let text = str::from_utf8(&self.src[start..self.pos]).expect("ascii digits");
+        if !float {
+            if let Ok(i) = text.parse::<i64>() {
+                return Ok(JsonValue::Int(i));
+            }
+        }
+        text.parse::<f64>()
+            .map(JsonValue::Number)
+            .map_err(|_| Error::Faild("invalid json number"))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        // skip the opening quote
+        self.pos += 1;
+        let mut buf = Vec::new();
+        loop {
+            faild!(self.pos >= self.src.len(), "unterminated json string");
+            let c = self.src[self.pos];
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    faild!(self.pos >= self.src.len(), "unterminated json string");
+                    let escaped = self.src[self.pos];
+                    self.pos += 1;
+                    match escaped {
+                        b'"' | b'\\' | b'/' => buf.push(escaped),
+                        b'n' => buf.push(b'\n'),
+                        b'r' => buf.push(b'\r'),
+                        b't' => buf.push(b'\t'),
+                        b'b' => buf.push(0x08),
+                        b'f' => buf.push(0x0c),
+                        b'u' => {
+                            let c = self.unicode()?;
+                            let mut utf8 = [0; 4];
+                            buf.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                        }
+                        _ => return Err(Error::Faild("invalid json string escape")),
+                    }
+                }
+                c => buf.push(c),
+            }
+        }
+        String::from_utf8(buf).map_err(|_| Error::Faild("json string is not utf8"))
+    }
+
+    fn unicode(&mut self) -> Result<char> {
+        let high = self.hex4()?;
+        if !(0xd800..=0xdbff).contains(&high) {
+            return Ok(char::from_u32(high).unwrap_or('\u{fffd}'));
+        }
+        // a surrogate pair
+        faild!(!self.src[self.pos..].starts_with(b"\\u"), "unpaired json surrogate");
+        self.pos += 2;
+        let low = self.hex4()?;
+        faild!(!(0xdc00..=0xdfff).contains(&low), "unpaired json surrogate");
+        let c = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+        Ok(char::from_u32(c).unwrap_or('\u{fffd}'))
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        faild!(self.src.len() < self.pos + 4, "invalid json unicode escape");
+        let text = str::from_utf8(&self.src[self.pos..self.pos + 4])
+            .map_err(|_| Error::Faild("invalid json unicode escape"))?;
+        self.pos += 4;
+        u32::from_str_radix(text, 16).map_err(|_| Error::Faild("invalid json unicode escape"))
+    }
+}
+
+/// Decoder of the `ReJSON-RL` type of RedisJSON, registered by default.
+pub struct RedisJsonHandler;
+
+impl ModuleTypeHandler for RedisJsonHandler {
+    fn decode(&self, _key: &[u8], encver: u32, reader: &mut ModuleReader) -> Result<ModuleValue> {
+        let json = match encver {
+            0 => {
+                let node_type = reader.load_unsigned()?;
+                load_node(reader, node_type)?
+            }
+            2 | 3 => JsonValue::parse(&reader.load_string()?)?,
+            _ => return Err(Error::Faild("unsupported ReJSON-RL encoding version")),
+        };
+        Ok(ModuleValue::Json(json))
+    }
+}
+
+/// a node of the RedisJSON 1.x tree, dict members are KEYVAL nodes.
+fn load_node(reader: &mut ModuleReader, node_type: u64) -> Result<JsonValue> {
+    let json = match node_type {
+        REJSON_NODE_NULL => JsonValue::Null,
+        REJSON_NODE_BOOLEAN => JsonValue::Bool(reader.load_string()?.first() == Some(&b'1')),
+        REJSON_NODE_INTEGER => JsonValue::Int(reader.load_signed()?),
+        REJSON_NODE_NUMBER => JsonValue::Number(reader.load_double()?),
+        REJSON_NODE_STRING => JsonValue::String(load_utf8(reader)?),
+        REJSON_NODE_DICT => {
+            let len = reader.load_unsigned()?;
+            let mut members = Vec::new();
+            for _ in 0..len {
+                faild!(reader.load_unsigned()? != REJSON_NODE_KEYVAL,
+                       "ReJSON-RL dict member is not a keyval node");
+                let key = load_utf8(reader)?;
+                let value_type = reader.load_unsigned()?;
+                members.push((key, load_node(reader, value_type)?));
+            }
+            JsonValue::Object(members)
+        }
+        REJSON_NODE_ARRAY => {
+            let len = reader.load_unsigned()?;
+            let mut items = Vec::new();
+            for _ in 0..len {
+                let item_type = reader.load_unsigned()?;
+                items.push(load_node(reader, item_type)?);
+            }
+            JsonValue::Array(items)
+        }
+        _ => return Err(Error::Faild("unknown ReJSON-RL node type")),
+    };
+    Ok(json)
+}
+
+fn load_utf8(reader: &mut ModuleReader) -> Result<String> {
+    String::from_utf8(reader.load_string()?).map_err(|_| Error::Faild("json string is not utf8"))
+}
@@ -0,0 +1,293 @@
+use com::*;
+use consts::*;
+use codec::{Length, RedisString};
+use std::collections::HashMap;
+
+mod json;
+pub use self::json::*;
+
+/// Decoder of the values saved by one module type, registered on the parser
+/// by the 9 characters name of the type, e.g. `ReJSON-RL`.
+pub trait ModuleTypeHandler {
+    /// decode the value of `key`, saved with encoding version `encver`.
+    ///
+    /// for `RDB_TYPE_MODULE_2` values, whatever is left unread by a handler
+    /// returning `ModuleValue::Opaque` is skipped. values of the deprecated
+    /// `RDB_TYPE_MODULE` carry no opcodes, so the handler must read them to
+    /// the end.
+    fn decode(&self, key: &[u8], encver: u32, reader: &mut ModuleReader) -> Result<ModuleValue>;
+
+    /// decode the global data a module saved with MODULE_AUX, `when` tells
+    /// if it was saved before or after the keyspace. skipped by default.
+    fn decode_aux(&self,
+                  _encver: u32,
+                  _when: u64,
+                  _reader: &mut ModuleReader)
+                  -> Result<ModuleValue> {
+        Ok(ModuleValue::Opaque)
+    }
+}
+
+/// What a `ModuleTypeHandler` made of a value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ModuleValue {
+    /// commands rebuilding the key, every command is a list of arguments.
+    Commands(Vec<Vec<Vec<u8>>>),
+    /// a JSON document, set by `JSON.SET key $ <json>`.
+    Json(JsonValue),
+    /// keep the serialized value as opaque bytes, replayed by RESTORE.
+    Opaque,
+}
+
+/// Handlers known by a parser, by module type name.
+#[derive(Default)]
+pub struct ModuleRegistry {
+    handlers: HashMap<String, Box<dyn ModuleTypeHandler>>,
+}
+
+impl ModuleRegistry {
+    pub fn register(&mut self, name: &str, handler: Box<dyn ModuleTypeHandler>) {
+        self.handlers.insert(name.to_owned(), handler);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn ModuleTypeHandler> {
+        self.handlers.get(name).map(|handler| &**handler)
+    }
+}
+
+/// Reads the fields of a module value the way `RedisModule_Load*` does.
+pub struct ModuleReader<'a> {
+    src: &'a [u8],
+    pos: usize,
+    // RDB_TYPE_MODULE_2 tags every field with its opcode
+    opcodes: bool,
+}
+
+impl<'a> ModuleReader<'a> {
+    fn new(src: &'a [u8], opcodes: bool) -> ModuleReader<'a> {
+        ModuleReader {
+            src,
+            pos: 0,
+            opcodes,
+        }
+    }
+
+    pub fn load_unsigned(&mut self) -> Result<u64> {
+        self.expect(REDIS_RDB_MODULE_OPCODE_UINT)?;
+        self.length()
+    }
+
+    pub fn load_signed(&mut self) -> Result<i64> {
+        self.expect(REDIS_RDB_MODULE_OPCODE_SINT)?;
+        Ok(self.length()? as i64)
+    }
+
+    pub fn load_string(&mut self) -> Result<Vec<u8>> {
+        self.expect(REDIS_RDB_MODULE_OPCODE_STRING)?;
+        self.string()
+    }
+
+    pub fn load_double(&mut self) -> Result<f64> {
+        self.expect(REDIS_RDB_MODULE_OPCODE_DOUBLE)?;
+        let src = self.bytes(8)?;
+        Ok(buf_to_f64(src))
+    }
+
+    pub fn load_float(&mut self) -> Result<f32> {
+        self.expect(REDIS_RDB_MODULE_OPCODE_FLOAT)?;
+        let src = self.bytes(4)?;
+        Ok(buf_to_f32(src))
+    }
+
+    /// bytes read so far.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn expect(&mut self, opcode: u8) -> Result<()> {
+        if !self.opcodes {
+            return Ok(());
+        }
+        let found = self.bytes(1)?[0];
+        faild!(found != opcode, "module value field of an unexpected type");
+        Ok(())
+    }
+
+    fn length(&mut self) -> Result<u64> {
+        more!(self.src.len() <= self.pos);
+        let length = Length::from_buf(&self.src[self.pos..])?;
+        self.pos += length.shift();
+        Ok(length.value())
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>> {
+        more!(self.src.len() <= self.pos);
+        let rs = RedisString::from_buf(&self.src[self.pos..])?;
+        self.pos += rs.shift();
+        Ok(rs.into_data())
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        more!(self.src.len() < self.pos + len);
+        let src = self.src;
+        self.pos += len;
+        Ok(&src[self.pos - len..self.pos])
+    }
+
+    /// opaque values are skipped, decoded ones must be read to their EOF.
+    fn finish(&mut self, value: &ModuleValue) -> Result<()> {
+        match value {
+            &ModuleValue::Opaque => self.skip_to_eof(),
+            _ => {
+                let eof = self.bytes(1)?[0];
+                faild!(eof != REDIS_RDB_MODULE_OPCODE_EOF,
+                       "module value not fully decoded by its handler");
+                Ok(())
+            }
+        }
+    }
+
+    /// walk the opcodes of a RDB_TYPE_MODULE_2 value up to its EOF.
+    fn skip_to_eof(&mut self) -> Result<()> {
+        loop {
+            let opcode = self.bytes(1)?[0];
+            match opcode {
+                REDIS_RDB_MODULE_OPCODE_EOF => return Ok(()),
+                REDIS_RDB_MODULE_OPCODE_SINT |
+                REDIS_RDB_MODULE_OPCODE_UINT => {
+                    self.length()?;
+                }
+                REDIS_RDB_MODULE_OPCODE_FLOAT => {
+                    self.bytes(4)?;
+                }
+                REDIS_RDB_MODULE_OPCODE_DOUBLE => {
+                    self.bytes(8)?;
+                }
+                REDIS_RDB_MODULE_OPCODE_STRING => {
+                    self.string()?;
+                }
+                _ => return Err(Error::Faild("unknown module value opcode")),
+            }
+        }
+    }
+}
+
+/// A value of a module type, decoded by its handler or skipped and replayed
+/// by RESTORE.
+#[derive(Clone, Debug)]
+pub struct RedisModule {
+    /// name of the module type, decoded from the module id.
+    pub name: String,
+    pub encver: u32,
+    /// `None` when no handler is registered for the type.
+    pub value: Option<ModuleValue>,
+    shift: usize,
+}
+
+impl Shift for RedisModule {
+    fn shift(&self) -> usize {
+        self.shift
+    }
+}
+
+impl RedisModule {
+    /// `src` starts at the module id, right after the key.
+    pub fn from_buf(src: &[u8],
+                    rtype: u8,
+                    key: &[u8],
+                    modules: &ModuleRegistry)
+                    -> Result<RedisModule> {
+        more!(src.is_empty());
+        let id = Length::from_buf(src)?;
+        let (name, encver) = module_type_name(id.value());
+        let opcodes = rtype == REDIS_RDB_TYPE_MODULE_2;
+        let mut reader = ModuleReader::new(&src[id.shift()..], opcodes);
+        let value = match modules.get(&name) {
+            Some(handler) => {
+                let value = handler.decode(key, encver, &mut reader)?;
+                if opcodes {
+                    reader.finish(&value)?;
+                }
+                Some(value)
+            }
+            None => {
+                faild!(!opcodes, "module value without opcodes needs a handler");
+                reader.skip_to_eof()?;
+                None
+            }
+        };
+        Ok(RedisModule {
+            name,
+            encver,
+            value,
+            shift: id.shift() + reader.position(),
+        })
+    }
+
+    /// true when the value is kept opaque, or was skipped for lack of a
+    /// handler.
+    pub fn needs_restore(&self) -> bool {
+        matches!(self.value, Some(ModuleValue::Opaque) | None)
+    }
+}
+
+/// Global data of a module, saved by MODULE_AUX (0xF7) out of any key.
+#[derive(Clone, Debug)]
+pub struct ModuleAux {
+    /// name of the module type, decoded from the module id.
+    pub name: String,
+    pub encver: u32,
+    /// `REDISMODULE_AUX_BEFORE_RDB` (1) or `REDISMODULE_AUX_AFTER_RDB` (2).
+    pub when: u64,
+    /// `None` when no handler is registered for the type.
+    pub value: Option<ModuleValue>,
+    shift: usize,
+}
+
+impl Shift for ModuleAux {
+    fn shift(&self) -> usize {
+        self.shift
+    }
+}
+
+impl ModuleAux {
+    /// `src` starts at the module id, right after the opcode.
+    pub fn from_buf(src: &[u8], modules: &ModuleRegistry) -> Result<ModuleAux> {
+        more!(src.is_empty());
+        let id = Length::from_buf(src)?;
+        let (name, encver) = module_type_name(id.value());
+        let mut reader = ModuleReader::new(&src[id.shift()..], true);
+        // `when` is always saved as an unsigned
+        let when = reader.load_unsigned()?;
+        let value = match modules.get(&name) {
+            Some(handler) => {
+                let value = handler.decode_aux(encver, when, &mut reader)?;
+                reader.finish(&value)?;
+                Some(value)
+            }
+            None => {
+                reader.skip_to_eof()?;
+                None
+            }
+        };
+        Ok(ModuleAux {
+            name,
+            encver,
+            when,
+            value,
+            shift: id.shift() + reader.position(),
+        })
+    }
+}
+
+/// the top 54 bits of a module id are 9 characters of 6 bits each, the low
+/// 10 bits are the encoding version.
+pub fn module_type_name(id: u64) -> (String, u32) {
+    let mut name = vec![0; 9];
+    let mut bits = id >> 10;
+    for i in (0..9).rev() {
+        name[i] = REDIS_MODULE_TYPE_NAME_CHARSET[(bits & 63) as usize];
+        bits >>= 6;
+    }
+    (String::from_utf8(name).expect("charset is ascii"), (id & 1023) as u32)
+}
@@ -2,6 +2,8 @@ use com::*;
 use consts::*;
 use codec::*;
 use fmt::*;
+use meta::KeyHint;
+use module::*;
 use std::time::{self, SystemTime};
 
 pub type Key = RedisString;
@@ -9,7 +11,12 @@ pub type Key = RedisString;
 pub type RedisLinkedList = RedisList<LinkedListItem>;
 pub type RedisSet = RedisList<LinkedListItem>;
 pub type RedisZSet = RedisList<ZSetItem>;
+pub type RedisZSet2 = RedisList<ZSet2Item>;
 pub type RedisHash = RedisList<HashItem>;
+/// nodes of a quicklist, every node is a ziplist dumped as a string.
+pub type RedisQuickList = RedisList<ZipListString>;
+/// nodes of a quicklist since redis 7.0, every node is a listpack or a plain item.
+pub type RedisQuickList2 = RedisList<QuickListNode>;
 
 #[derive(Debug, Clone)]
 pub enum RedisData {
@@ -20,6 +27,7 @@ pub enum RedisData {
     Set(Key, RedisSet),
     // ZSet
     ZSet(Key, RedisZSet),
+    ZSet2(Key, RedisZSet2),
     /// this should never support in reids 3.0
     Hash(Key, RedisHash),
 
@@ -28,20 +36,54 @@ pub enum RedisData {
     ZSetZipList(Key, RedisString),
     HashZipList(Key, RedisString),
     SetIntSet(Key, RedisString),
+    HashZipMap(Key, ZipMapString),
+    ListQuickList(Key, RedisQuickList),
+    ListQuickList2(Key, RedisQuickList2),
+    HashListPack(Key, ListPackString),
+    ZSetListPack(Key, ListPackString),
+    SetListPack(Key, ListPackString),
+    Stream(Key, RedisStream),
+    Module(Key, RedisModule),
+    HashMetadata(Key, HashMetadata),
+    HashListPackEx(Key, HashListPackEx),
 }
 
 impl RedisData {
     pub fn copy_key(&self) -> RedisString {
+        self.key().clone()
+    }
+
+    pub fn key(&self) -> &Key {
         match self {
-            &RedisData::String(ref key, _) => key.clone(),
-            &RedisData::List(ref key, _) => key.clone(),
-            &RedisData::Set(ref key, _) => key.clone(),
-            &RedisData::ZSet(ref key, _) => key.clone(),
-            &RedisData::Hash(ref key, _) => key.clone(),
-            &RedisData::ListZipList(ref key, _) => key.clone(),
-            &RedisData::ZSetZipList(ref key, _) => key.clone(),
-            &RedisData::HashZipList(ref key, _) => key.clone(),
-            &RedisData::SetIntSet(ref key, _) => key.clone(),
+            RedisData::String(key, _) => key,
+            RedisData::List(key, _) => key,
+            RedisData::Set(key, _) => key,
+            RedisData::ZSet(key, _) => key,
+            RedisData::ZSet2(key, _) => key,
+            RedisData::Hash(key, _) => key,
+            RedisData::ListZipList(key, _) => key,
+            RedisData::ZSetZipList(key, _) => key,
+            RedisData::HashZipList(key, _) => key,
+            RedisData::SetIntSet(key, _) => key,
+            RedisData::HashZipMap(key, _) => key,
+            RedisData::ListQuickList(key, _) => key,
+            RedisData::ListQuickList2(key, _) => key,
+            RedisData::HashListPack(key, _) => key,
+            RedisData::ZSetListPack(key, _) => key,
+            RedisData::SetListPack(key, _) => key,
+            RedisData::Stream(key, _) => key,
+            RedisData::Module(key, _) => key,
+            RedisData::HashMetadata(key, _) => key,
+            RedisData::HashListPackEx(key, _) => key,
+        }
+    }
+
+    /// module values kept as opaque bytes, or without a handler, are
+    /// replayed by RESTORE.
+    pub fn needs_restore(&self) -> bool {
+        match self {
+            RedisData::Module(_, module) => module.needs_restore(),
+            _ => false,
         }
     }
 }
@@ -55,7 +97,7 @@ impl RedisFormat for RedisData {
                 buf.push(RedisFmt::Raw(rs.into_data()));
             }
             RedisData::List(key, rl) => {
-                buf.push(RedisFmt::Cmd("LPUSH"));
+                buf.push(RedisFmt::Cmd("RPUSH"));
                 buf.push(RedisFmt::Raw(key.into_data()));
                 let RedisList { items, .. } = rl;
                 for linked_list_item in items {
@@ -79,12 +121,20 @@ impl RedisFormat for RedisData {
                     buf.push(RedisFmt::Raw(member.into_data()));
                 }
             }
+            RedisData::ZSet2(key, RedisList { items, .. }) => {
+                buf.push(RedisFmt::Cmd("ZADD"));
+                buf.push(RedisFmt::Raw(key.into_data()));
+                for ZSet2Item { member, score } in items {
+                    buf.push(RedisFmt::Raw(fmt_double(score).into_bytes()));
+                    buf.push(RedisFmt::Raw(member.into_data()));
+                }
+            }
             RedisData::Hash(key, RedisList { items, .. }) => {
 
                 buf.push(RedisFmt::Cmd("HSET"));
                 buf.push(RedisFmt::Raw(key.into_data()));
                 for item in items {
-                    let HashItem { key: hkey, value } = item;
+                    let HashItem { key: hkey, value, .. } = item;
                     buf.push(RedisFmt::Raw(hkey.into_data()));
                     buf.push(RedisFmt::Raw(value.into_data()));
                 }
@@ -105,7 +155,7 @@ impl RedisFormat for RedisData {
                 let local_buf = rs.into_data();
                 let ZipList { entries, .. } = ZipList::from_buf(&local_buf)
                     .expect("faild to parse ziplist list");
-                buf.push(RedisFmt::Cmd("LPUSH"));
+                buf.push(RedisFmt::Cmd("RPUSH"));
                 buf.push(RedisFmt::Raw(key.into_data()));
                 let sp_data = entries.into_iter().map(|ZipListEntry { sp, .. }| sp);
                 for data in sp_data {
@@ -113,6 +163,15 @@ impl RedisFormat for RedisData {
                 }
 
             }
+            RedisData::ListQuickList(key, RedisList { items, .. }) => {
+                buf.push(RedisFmt::Cmd("RPUSH"));
+                buf.push(RedisFmt::Raw(key.into_data()));
+                for node in items {
+                    for data in node.datas {
+                        buf.push(RedisFmt::Raw(data));
+                    }
+                }
+            }
             RedisData::HashZipList(key, rs) => {
                 let local_buf = rs.into_data();
                 let ZipList { entries, .. } = ZipList::from_buf(&local_buf)
@@ -124,6 +183,14 @@ impl RedisFormat for RedisData {
                     buf.push(RedisFmt::Raw(data));
                 }
             }
+            RedisData::HashZipMap(key, zm) => {
+                buf.push(RedisFmt::Cmd("HSET"));
+                buf.push(RedisFmt::Raw(key.into_data()));
+                for ZipMapEntry { key: hkey, value, .. } in zm.entries {
+                    buf.push(RedisFmt::Raw(hkey));
+                    buf.push(RedisFmt::Raw(value));
+                }
+            }
             RedisData::ZSetZipList(key, rs) => {
                 let local_buf = rs.into_data();
                 let ZipList { entries, .. } = ZipList::from_buf(&local_buf)
@@ -131,18 +198,48 @@ impl RedisFormat for RedisData {
                 buf.push(RedisFmt::Cmd("ZADD"));
                 buf.push(RedisFmt::Raw(key.into_data()));
                 let sp_data = entries.into_iter().map(|ZipListEntry { sp, .. }| sp.into_data());
-                let mut is_score = false;
-                for data in sp_data {
-                    if is_score {
+                fmt_zset_pairs(sp_data, buf);
+            }
+            RedisData::HashListPack(key, lp) => {
+                buf.push(RedisFmt::Cmd("HSET"));
+                buf.push(RedisFmt::Raw(key.into_data()));
+                for data in lp.datas {
+                    buf.push(RedisFmt::Raw(data));
+                }
+            }
+            RedisData::ZSetListPack(key, lp) => {
+                buf.push(RedisFmt::Cmd("ZADD"));
+                buf.push(RedisFmt::Raw(key.into_data()));
+                fmt_zset_pairs(lp.datas.into_iter(), buf);
+            }
+            RedisData::Stream(key, stream) => return fmt_stream(key.into_data(), stream, buf),
+            RedisData::Module(key, module) => return fmt_module(key.into_data(), module, buf),
+            RedisData::HashMetadata(key, hash) => {
+                let fields = hash.items
+                    .into_iter()
+                    .map(|HashItem { key, value, expire, .. }| {
+                        (key.into_data(), value.into_data(), expire)
+                    })
+                    .collect();
+                return fmt_hash_ex(key.into_data(), fields, buf);
+            }
+            RedisData::HashListPackEx(key, hash) => {
+                return fmt_hash_ex(key.into_data(), hash.fields, buf);
+            }
+            RedisData::SetListPack(key, lp) => {
+                buf.push(RedisFmt::Cmd("SADD"));
+                buf.push(RedisFmt::Raw(key.into_data()));
+                for data in lp.datas {
+                    buf.push(RedisFmt::Raw(data));
+                }
+            }
+            RedisData::ListQuickList2(key, RedisList { items, .. }) => {
+                buf.push(RedisFmt::Cmd("RPUSH"));
+                buf.push(RedisFmt::Raw(key.into_data()));
+                for node in items {
+                    for data in node.into_data() {
                         buf.push(RedisFmt::Raw(data));
-                        continue;
                     }
-                    let value = String::from_utf8(data)
-                        .ok()
-                        .and_then(|fv| fv.parse::<f64>().ok())
-                        .expect("abort parse: wrong float format");
-                    buf.push(RedisFmt::Raw(format!("{}", value).into_bytes()));
-                    is_score = !is_score;
                 }
             }
         };
@@ -151,9 +248,174 @@ impl RedisFormat for RedisData {
     }
 }
 
+// ziplist and listpack keep a sorted set as member, score, member, score...
+fn fmt_zset_pairs<I: Iterator<Item = Vec<u8>>>(datas: I, buf: &mut Vec<RedisFmt>) {
+    let mut member = None;
+    for data in datas {
+        match member.take() {
+            None => member = Some(data),
+            Some(member) => {
+                buf.push(RedisFmt::Raw(data));
+                buf.push(RedisFmt::Raw(member));
+            }
+        }
+    }
+}
+
+/// HSET every field, then HPEXPIREAT the fields which have a TTL.
+fn fmt_hash_ex(key: Vec<u8>,
+               fields: Vec<HashField>,
+               buf: &mut Vec<RedisFmt>)
+               -> usize {
+    let mut expires = Vec::new();
+    buf.push(RedisFmt::Cmd("HSET"));
+    buf.push(RedisFmt::Raw(key.clone()));
+    for (field, value, expire) in fields {
+        if let Some(expire) = expire {
+            expires.push((field.clone(), expire));
+        }
+        buf.push(RedisFmt::Raw(field));
+        buf.push(RedisFmt::Raw(value));
+    }
+    buf.push(RedisFmt::CRLF);
+
+    let count = 1 + expires.len();
+    for (field, expire) in expires {
+        buf.push(RedisFmt::Cmd("HPEXPIREAT"));
+        buf.push(RedisFmt::Raw(key.clone()));
+        buf.push(RedisFmt::Raw(format!("{}", expire).into_bytes()));
+        buf.push(RedisFmt::Cmd("FIELDS"));
+        buf.push(RedisFmt::Cmd("1"));
+        buf.push(RedisFmt::Raw(field));
+        buf.push(RedisFmt::CRLF);
+    }
+    count
+}
+
+/// rebuild a stream the way redis rewrites it into an AOF: XADD every entry,
+/// XSETID the last id, then create the groups and claim their pending ids.
+fn fmt_stream(key: Vec<u8>, stream: RedisStream, buf: &mut Vec<RedisFmt>) -> usize {
+    let mut count = 0;
+    let RedisStream { entries, last_id, max_deleted_id, entries_added, groups, .. } = stream;
+    if entries.is_empty() {
+        // XADD with MAXLEN 0 creates the key without keeping the entry
+        buf.push(RedisFmt::Cmd("XADD"));
+        buf.push(RedisFmt::Raw(key.clone()));
+        buf.push(RedisFmt::Cmd("MAXLEN"));
+        buf.push(RedisFmt::Cmd("0"));
+        buf.push(RedisFmt::Cmd("0-1"));
+        buf.push(RedisFmt::Cmd("x"));
+        buf.push(RedisFmt::Cmd("y"));
+        buf.push(RedisFmt::CRLF);
+        count += 1;
+    }
+    for StreamEntry { id, fields } in entries {
+        buf.push(RedisFmt::Cmd("XADD"));
+        buf.push(RedisFmt::Raw(key.clone()));
+        buf.push(RedisFmt::Raw(id.to_string().into_bytes()));
+        for (field, value) in fields {
+            buf.push(RedisFmt::Raw(field));
+            buf.push(RedisFmt::Raw(value));
+        }
+        buf.push(RedisFmt::CRLF);
+        count += 1;
+    }
+
+    buf.push(RedisFmt::Cmd("XSETID"));
+    buf.push(RedisFmt::Raw(key.clone()));
+    buf.push(RedisFmt::Raw(last_id.to_string().into_bytes()));
+    if let (Some(entries_added), Some(max_deleted_id)) = (entries_added, max_deleted_id) {
+        buf.push(RedisFmt::Cmd("ENTRIESADDED"));
+        buf.push(RedisFmt::Raw(format!("{}", entries_added).into_bytes()));
+        buf.push(RedisFmt::Cmd("MAXDELETEDID"));
+        buf.push(RedisFmt::Raw(max_deleted_id.to_string().into_bytes()));
+    }
+    buf.push(RedisFmt::CRLF);
+    count += 1;
+
+    for StreamGroup { name, last_id, entries_read, pending, consumers } in groups {
+        buf.push(RedisFmt::Cmd("XGROUP"));
+        buf.push(RedisFmt::Cmd("CREATE"));
+        buf.push(RedisFmt::Raw(key.clone()));
+        buf.push(RedisFmt::Raw(name.clone()));
+        buf.push(RedisFmt::Raw(last_id.to_string().into_bytes()));
+        if let Some(entries_read) = entries_read {
+            buf.push(RedisFmt::Cmd("ENTRIESREAD"));
+            buf.push(RedisFmt::Raw(format!("{}", entries_read).into_bytes()));
+        }
+        buf.push(RedisFmt::CRLF);
+        count += 1;
+
+        for consumer in consumers {
+            if consumer.pending.is_empty() {
+                buf.push(RedisFmt::Cmd("XGROUP"));
+                buf.push(RedisFmt::Cmd("CREATECONSUMER"));
+                buf.push(RedisFmt::Raw(key.clone()));
+                buf.push(RedisFmt::Raw(name.clone()));
+                buf.push(RedisFmt::Raw(consumer.name.clone()));
+                buf.push(RedisFmt::CRLF);
+                count += 1;
+            }
+            for id in consumer.pending {
+                // delivery time and count live in the group PEL
+                let nack = pending.iter().find(|nack| nack.id == id);
+                buf.push(RedisFmt::Cmd("XCLAIM"));
+                buf.push(RedisFmt::Raw(key.clone()));
+                buf.push(RedisFmt::Raw(name.clone()));
+                buf.push(RedisFmt::Raw(consumer.name.clone()));
+                buf.push(RedisFmt::Cmd("0"));
+                buf.push(RedisFmt::Raw(id.to_string().into_bytes()));
+                if let Some(nack) = nack {
+                    buf.push(RedisFmt::Cmd("TIME"));
+                    buf.push(RedisFmt::Raw(format!("{}", nack.delivery_time).into_bytes()));
+                    buf.push(RedisFmt::Cmd("RETRYCOUNT"));
+                    buf.push(RedisFmt::Raw(format!("{}", nack.delivery_count).into_bytes()));
+                }
+                buf.push(RedisFmt::Cmd("JUSTID"));
+                buf.push(RedisFmt::Cmd("FORCE"));
+                buf.push(RedisFmt::CRLF);
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// the commands given by the handler, skipped and opaque values emit nothing
+/// here as they are replayed by RESTORE.
+fn fmt_module(key: Vec<u8>, module: RedisModule, buf: &mut Vec<RedisFmt>) -> usize {
+    match module.value {
+        Some(ModuleValue::Commands(cmds)) => {
+            let count = cmds.len();
+            for cmd in cmds {
+                for arg in cmd {
+                    buf.push(RedisFmt::Raw(arg));
+                }
+                buf.push(RedisFmt::CRLF);
+            }
+            count
+        }
+        Some(ModuleValue::Json(json)) => {
+            buf.push(RedisFmt::Cmd("JSON.SET"));
+            buf.push(RedisFmt::Raw(key));
+            buf.push(RedisFmt::Cmd("$"));
+            buf.push(RedisFmt::Raw(json.to_json().into_bytes()));
+            buf.push(RedisFmt::CRLF);
+            1
+        }
+        _ => 0,
+    }
+}
+
 impl FromBuf for RedisData {
-    /// always right otherwise panic forever
     fn from_buf(src: &[u8]) -> Result<Self> {
+        RedisData::from_buf_with(src, &ModuleRegistry::default())
+    }
+}
+
+impl RedisData {
+    /// always right otherwise panic forever
+    pub fn from_buf_with(src: &[u8], modules: &ModuleRegistry) -> Result<Self> {
         let ltype = src[0];
         let key = RedisString::from_buf(&src[1..])?;
         let src = &src[1 + key.shift()..];
@@ -174,15 +436,26 @@ impl FromBuf for RedisData {
                 let rzls = RedisList::from_buf(src)?;
                 Ok(RedisData::ZSet(key, rzls))
             }
+            REDIS_RDB_TYPE_ZSET_2 => {
+                let rzls = RedisList::from_buf(src)?;
+                Ok(RedisData::ZSet2(key, rzls))
+            }
             REDIS_RDB_TYPE_HASH => {
                 let rhls = RedisList::from_buf(src)?;
                 Ok(RedisData::Hash(key, rhls))
             }
-            REDIS_RDB_TYPE_HASH_ZIPMAP => panic!("not support zipmap"),
+            REDIS_RDB_TYPE_HASH_ZIPMAP => {
+                let zm = ZipMapString::from_buf(src)?;
+                Ok(RedisData::HashZipMap(key, zm))
+            }
             REDIS_RDB_TYPE_LIST_ZIPLIST => {
                 let rs = RedisString::from_buf(src)?;
                 Ok(RedisData::ListZipList(key, rs))
             }
+            REDIS_RDB_TYPE_LIST_QUICKLIST => {
+                let rqls = RedisList::from_buf(src)?;
+                Ok(RedisData::ListQuickList(key, rqls))
+            }
             REDIS_RDB_TYPE_SET_INTSET => {
                 let rs = RedisString::from_buf(src)?;
                 Ok(RedisData::SetIntSet(key, rs))
@@ -195,6 +468,45 @@ impl FromBuf for RedisData {
                 let rs = RedisString::from_buf(src)?;
                 Ok(RedisData::HashZipList(key, rs))
             }
+            REDIS_RDB_TYPE_HASH_LISTPACK => {
+                let lp = ListPackString::from_buf(src)?;
+                faild!(lp.datas.len() % 2 == 1, "hash field without a value");
+                Ok(RedisData::HashListPack(key, lp))
+            }
+            REDIS_RDB_TYPE_ZSET_LISTPACK => {
+                let lp = ListPackString::from_buf(src)?;
+                faild!(lp.datas.len() % 2 == 1, "sorted set member without a score");
+                Ok(RedisData::ZSetListPack(key, lp))
+            }
+            REDIS_RDB_TYPE_LIST_QUICKLIST_2 => {
+                let rqls = RedisList::from_buf(src)?;
+                Ok(RedisData::ListQuickList2(key, rqls))
+            }
+            REDIS_RDB_TYPE_STREAM_LISTPACKS |
+            REDIS_RDB_TYPE_STREAM_LISTPACKS_2 |
+            REDIS_RDB_TYPE_STREAM_LISTPACKS_3 => {
+                let stream = RedisStream::from_buf(src, ltype)?;
+                Ok(RedisData::Stream(key, stream))
+            }
+            REDIS_RDB_TYPE_SET_LISTPACK => {
+                let lp = ListPackString::from_buf(src)?;
+                Ok(RedisData::SetListPack(key, lp))
+            }
+            REDIS_RDB_TYPE_HASH_METADATA_PRE_GA |
+            REDIS_RDB_TYPE_HASH_METADATA => {
+                let hash = HashMetadata::from_buf(src, ltype)?;
+                Ok(RedisData::HashMetadata(key, hash))
+            }
+            REDIS_RDB_TYPE_HASH_LISTPACK_EX_PRE_GA |
+            REDIS_RDB_TYPE_HASH_LISTPACK_EX => {
+                let hash = HashListPackEx::from_buf(src, ltype)?;
+                Ok(RedisData::HashListPackEx(key, hash))
+            }
+            REDIS_RDB_TYPE_MODULE |
+            REDIS_RDB_TYPE_MODULE_2 => {
+                let module = RedisModule::from_buf(src, ltype, &key.as_bytes(), modules)?;
+                Ok(RedisData::Module(key, module))
+            }
             _ => unreachable!(),
         }
     }
@@ -204,15 +516,26 @@ impl Shift for RedisData {
     #[inline]
     fn shift(&self) -> usize {
         let suffix_len = match self {
-            &RedisData::String(ref key, ref v) => key.shift() + v.shift(),
-            &RedisData::List(ref key, ref v) => key.shift() + v.shift(),
-            &RedisData::Set(ref key, ref v) => key.shift() + v.shift(),
-            &RedisData::ZSet(ref key, ref v) => key.shift() + v.shift(),
-            &RedisData::Hash(ref key, ref v) => key.shift() + v.shift(),
-            &RedisData::ListZipList(ref key, ref v) => key.shift() + v.shift(),
-            &RedisData::SetIntSet(ref key, ref v) => key.shift() + v.shift(),
-            &RedisData::HashZipList(ref key, ref v) => key.shift() + v.shift(),
-            &RedisData::ZSetZipList(ref key, ref v) => key.shift() + v.shift(),
+            RedisData::String(key, v) => key.shift() + v.shift(),
+            RedisData::List(key, v) => key.shift() + v.shift(),
+            RedisData::Set(key, v) => key.shift() + v.shift(),
+            RedisData::ZSet(key, v) => key.shift() + v.shift(),
+            RedisData::ZSet2(key, v) => key.shift() + v.shift(),
+            RedisData::Hash(key, v) => key.shift() + v.shift(),
+            RedisData::ListZipList(key, v) => key.shift() + v.shift(),
+            RedisData::SetIntSet(key, v) => key.shift() + v.shift(),
+            RedisData::HashZipList(key, v) => key.shift() + v.shift(),
+            RedisData::ZSetZipList(key, v) => key.shift() + v.shift(),
+            RedisData::HashZipMap(key, v) => key.shift() + v.shift(),
+            RedisData::ListQuickList(key, v) => key.shift() + v.shift(),
+            RedisData::ListQuickList2(key, v) => key.shift() + v.shift(),
+            RedisData::HashListPack(key, v) => key.shift() + v.shift(),
+            RedisData::ZSetListPack(key, v) => key.shift() + v.shift(),
+            RedisData::SetListPack(key, v) => key.shift() + v.shift(),
+            RedisData::Stream(key, v) => key.shift() + v.shift(),
+            RedisData::Module(key, v) => key.shift() + v.shift(),
+            RedisData::HashMetadata(key, v) => key.shift() + v.shift(),
+            RedisData::HashListPackEx(key, v) => key.shift() + v.shift(),
         };
         1 + suffix_len
     }
@@ -230,9 +553,9 @@ pub enum ExpireTime {
 impl Shift for ExpireTime {
     #[inline]
     fn shift(&self) -> usize {
-        match self {
-            &ExpireTime::Ms(_) => 8 + 1,
-            &ExpireTime::Sec(_) => 4 + 1,
+        match *self {
+            ExpireTime::Ms(_) => 8 + 1,
+            ExpireTime::Sec(_) => 4 + 1,
             _ => 0,
         }
     }
@@ -253,7 +576,7 @@ impl ExpireTime {
                     .duration_since(time::UNIX_EPOCH)
                     .expect("system timer is too busy")
                     .as_secs();
-                let sec = ms as u64 / 1000;
+                let sec = ms / 1000;
                 if now > sec {
                     return 0;
                 }
@@ -286,6 +609,20 @@ impl ExpireTime {
 
 
 impl ExpireTime {
+    /// unix time in ms the key expires at.
+    pub fn unix_ms(&self) -> Option<u64> {
+        match *self {
+            ExpireTime::Ms(ms) => Some(ms),
+            ExpireTime::Sec(sec) => Some(sec as u64 * 1000),
+            ExpireTime::None => None,
+        }
+    }
+
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        matches!(*self, ExpireTime::None)
+    }
+
     #[inline]
     pub fn expire_in_ms(src: &[u8]) -> Result<ExpireTime> {
         other!(src[0] != REDIS_RDB_OPCODE_EXPIRETIME_MS);
@@ -300,3 +637,111 @@ impl ExpireTime {
         Ok(ExpireTime::Sec(buf_to_u32(&src[1..])))
     }
 }
+
+
+/// LRU/LFU information saved in front of a key under an LRU or LFU
+/// maxmemory-policy.
+#[derive(Clone, Debug)]
+pub enum Eviction {
+    /// seconds since the last access.
+    Idle(Length),
+    /// logarithmic access counter.
+    Freq(u8),
+    None,
+}
+
+impl Shift for Eviction {
+    #[inline]
+    fn shift(&self) -> usize {
+        match self {
+            Eviction::Idle(idle) => 1 + idle.shift(),
+            Eviction::Freq(_) => 1 + 1,
+            Eviction::None => 0,
+        }
+    }
+}
+
+impl FromBuf for Eviction {
+    fn from_buf(src: &[u8]) -> Result<Eviction> {
+        more!(src.is_empty());
+        match src[0] {
+            REDIS_RDB_OPCODE_IDLE => Ok(Eviction::Idle(Length::from_buf(&src[1..])?)),
+            REDIS_RDB_OPCODE_FREQ => {
+                more!(src.len() < 1 + 1);
+                Ok(Eviction::Freq(src[1]))
+            }
+            _ => Ok(Eviction::None),
+        }
+    }
+}
+
+impl Eviction {
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        matches!(*self, Eviction::None)
+    }
+
+    pub fn to_hint(&self, db: usize, key: Vec<u8>) -> KeyHint {
+        let (idle, freq) = match self {
+            Eviction::Idle(idle) => (Some(idle.value()), None),
+            Eviction::Freq(freq) => (None, Some(*freq)),
+            Eviction::None => (None, None),
+        };
+        KeyHint {
+            db,
+            key,
+            idle,
+            freq,
+        }
+    }
+}
+
+/// the payload DUMP would return for a value: rdb type, serialized value,
+/// rdb version and the CRC64 of all of them.
+pub fn dump_payload(rtype: u8, value: &[u8], version: u32) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(1 + value.len() + 2 + 8);
+    payload.push(rtype);
+    payload.extend_from_slice(value);
+    payload.push(version as u8);
+    payload.push((version >> 8) as u8);
+    let crc = crc64(0, &payload);
+    for i in 0..8 {
+        payload.push((crc >> (8 * i)) as u8);
+    }
+    payload
+}
+
+/// RESTORE a key from its DUMP payload, keeping its TTL and eviction hint.
+pub fn fmt_restore(key: RedisString,
+                   payload: Vec<u8>,
+                   expire: ExpireTime,
+                   eviction: Eviction,
+                   buf: &mut Vec<RedisFmt>)
+                   -> usize {
+    let ttl = match expire {
+        ExpireTime::Ms(ms) => ms,
+        ExpireTime::Sec(sec) => sec as u64 * 1000,
+        ExpireTime::None => 0,
+    };
+    buf.push(RedisFmt::Cmd("RESTORE"));
+    buf.push(RedisFmt::Raw(key.into_data()));
+    buf.push(RedisFmt::Raw(format!("{}", ttl).into_bytes()));
+    buf.push(RedisFmt::Raw(payload));
+    buf.push(RedisFmt::Cmd("REPLACE"));
+    if !expire.is_none() {
+        buf.push(RedisFmt::Cmd("ABSTTL"));
+    }
+    match eviction {
+        Eviction::Idle(idle) => {
+            buf.push(RedisFmt::Cmd("IDLETIME"));
+            buf.push(RedisFmt::Raw(format!("{}", idle.value()).into_bytes()));
+        }
+        Eviction::Freq(freq) => {
+            buf.push(RedisFmt::Cmd("FREQ"));
+            buf.push(RedisFmt::Raw(format!("{}", freq).into_bytes()));
+        }
+        Eviction::None => {}
+    }
+    buf.push(RedisFmt::CRLF);
+    1
+}
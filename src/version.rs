@@ -0,0 +1,93 @@
+use com::*;
+use consts::*;
+
+/// the first rdb version a value type or opcode can appear in, `None` for
+/// codes newbee does not know.
+pub fn since(code: u8) -> Option<u32> {
+    let version = match code {
+        REDIS_RDB_TYPE_STRING |
+        REDIS_RDB_TYPE_LIST |
+        REDIS_RDB_TYPE_SET |
+        REDIS_RDB_TYPE_ZSET |
+        REDIS_RDB_TYPE_HASH => 1,
+        // the encodings of redis 2.x, whose rdb versions were not bumped
+        // for each of them
+        REDIS_RDB_TYPE_HASH_ZIPMAP |
+        REDIS_RDB_TYPE_LIST_ZIPLIST |
+        REDIS_RDB_TYPE_SET_INTSET |
+        REDIS_RDB_TYPE_ZSET_ZIPLIST => 1,
+        // redis 2.6
+        REDIS_RDB_TYPE_HASH_ZIPLIST => 6,
+        // redis 3.2
+        REDIS_RDB_TYPE_LIST_QUICKLIST => 7,
+        // redis 4.0
+        REDIS_RDB_TYPE_ZSET_2 |
+        REDIS_RDB_TYPE_MODULE |
+        REDIS_RDB_TYPE_MODULE_2 => 8,
+        // redis 5.0
+        REDIS_RDB_TYPE_STREAM_LISTPACKS => 9,
+        // redis 7.0
+        REDIS_RDB_TYPE_HASH_LISTPACK |
+        REDIS_RDB_TYPE_ZSET_LISTPACK |
+        REDIS_RDB_TYPE_LIST_QUICKLIST_2 |
+        REDIS_RDB_TYPE_STREAM_LISTPACKS_2 => 10,
+        // redis 7.2 and valkey 8
+        REDIS_RDB_TYPE_SET_LISTPACK |
+        REDIS_RDB_TYPE_STREAM_LISTPACKS_3 => 11,
+        // redis 7.4
+        REDIS_RDB_TYPE_HASH_METADATA_PRE_GA |
+        REDIS_RDB_TYPE_HASH_LISTPACK_EX_PRE_GA |
+        REDIS_RDB_TYPE_HASH_METADATA |
+        REDIS_RDB_TYPE_HASH_LISTPACK_EX => 12,
+
+        REDIS_RDB_OPCODE_SELECTDB |
+        REDIS_RDB_OPCODE_EOF |
+        REDIS_RDB_OPCODE_EXPIRETIME => 1,
+        REDIS_RDB_OPCODE_EXPIRETIME_MS => 3,
+        REDIS_RDB_OPCODE_AUX |
+        REDIS_RDB_OPCODE_RESIZEDB => 7,
+        REDIS_RDB_OPCODE_MODULE_AUX |
+        REDIS_RDB_OPCODE_IDLE |
+        REDIS_RDB_OPCODE_FREQ => 9,
+        REDIS_RDB_OPCODE_FUNCTION_PRE_GA |
+        REDIS_RDB_OPCODE_FUNCTION2 => 10,
+        // valkey 8 saves the slot sizes of a cluster node
+        REDIS_RDB_OPCODE_SLOT_INFO => 11,
+        _ => return None,
+    };
+    Some(version)
+}
+
+/// the rdb version is in the range newbee can parse.
+pub fn check_version(version: u32) -> Result<()> {
+    if !(REDIS_RDB_VERSION_MIN..=REDIS_RDB_VERSION_MAX).contains(&version) {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+/// `code` is a value type or opcode a rdb of `version` can hold.
+pub fn check_code(version: u32, code: u8) -> Result<()> {
+    match since(code) {
+        Some(since) if since <= version => Ok(()),
+        _ => {
+            Err(Error::UnsupportedType {
+                code,
+                version,
+            })
+        }
+    }
+}
+
+/// like `check_code`, for the byte in front of a key which must be a value
+/// type and not an opcode.
+pub fn check_type(version: u32, rtype: u8) -> Result<()> {
+    // SLOT_INFO is the lowest opcode
+    if rtype >= REDIS_RDB_OPCODE_SLOT_INFO {
+        return Err(Error::UnsupportedType {
+            code: rtype,
+            version,
+        });
+    }
+    check_code(version, rtype)
+}
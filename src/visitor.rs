@@ -0,0 +1,215 @@
+use com::*;
+use codec::*;
+use types::*;
+use module::ModuleValue;
+use std::str;
+
+/// Callbacks in the style of rdb-tools, every one of them does nothing by
+/// default. `expire` is the unix time in ms a key expires at.
+#[allow(unused_variables)]
+pub trait RdbVisitor {
+    fn start_rdb(&mut self, version: u32) {}
+    fn aux_field(&mut self, key: &[u8], value: &[u8]) {}
+    fn start_database(&mut self, db: usize) {}
+    fn resize_db(&mut self, db_size: usize, expires_size: usize) {}
+
+    fn set(&mut self, key: &[u8], value: &[u8], expire: Option<u64>) {}
+
+    fn start_hash(&mut self, key: &[u8], length: usize, expire: Option<u64>) {}
+    /// `field_expire` is only set for hashes with field TTLs.
+    fn hset(&mut self, key: &[u8], field: &[u8], value: &[u8], field_expire: Option<u64>) {}
+    fn end_hash(&mut self, key: &[u8]) {}
+
+    fn start_list(&mut self, key: &[u8], expire: Option<u64>) {}
+    fn rpush(&mut self, key: &[u8], value: &[u8]) {}
+    fn end_list(&mut self, key: &[u8]) {}
+
+    fn start_set(&mut self, key: &[u8], cardinality: usize, expire: Option<u64>) {}
+    fn sadd(&mut self, key: &[u8], member: &[u8]) {}
+    fn end_set(&mut self, key: &[u8]) {}
+
+    fn start_sorted_set(&mut self, key: &[u8], length: usize, expire: Option<u64>) {}
+    fn zadd(&mut self, key: &[u8], score: f64, member: &[u8]) {}
+    fn end_sorted_set(&mut self, key: &[u8]) {}
+
+    fn stream(&mut self, key: &[u8], stream: &RedisStream, expire: Option<u64>) {}
+    /// `value` is `None` when no handler is registered for the module type.
+    fn module(&mut self,
+              key: &[u8],
+              name: &str,
+              value: Option<&ModuleValue>,
+              expire: Option<u64>) {
+    }
+    fn function(&mut self, library: &[u8]) {}
+
+    fn end_database(&mut self, db: usize) {}
+    fn end_rdb(&mut self) {}
+}
+
+/// call the callbacks of one key, decoding the embedded ziplists, listpacks
+/// and intsets on the way.
+pub fn visit_data<V: RdbVisitor>(data: &RedisData,
+                                 expire: ExpireTime,
+                                 visitor: &mut V)
+                                 -> Result<()> {
+    let expire = expire.unix_ms();
+    let key = data.key().as_bytes();
+    let key = &key[..];
+    match data {
+        RedisData::String(_, value) => {
+            visitor.set(key, &value.as_bytes(), expire);
+        }
+        RedisData::List(_, RedisList { items, .. }) => {
+            visitor.start_list(key, expire);
+            for LinkedListItem(value) in items {
+                visitor.rpush(key, &value.as_bytes());
+            }
+            visitor.end_list(key);
+        }
+        RedisData::ListZipList(_, rs) => {
+            let ZipList { entries, .. } = ZipList::from_buf(&rs.as_bytes())?;
+            visitor.start_list(key, expire);
+            for ZipListEntry { sp, .. } in entries {
+                visitor.rpush(key, &sp.into_data());
+            }
+            visitor.end_list(key);
+        }
+        RedisData::ListQuickList(_, RedisList { items, .. }) => {
+            visitor.start_list(key, expire);
+            for node in items {
+                for value in &node.datas {
+                    visitor.rpush(key, value);
+                }
+            }
+            visitor.end_list(key);
+        }
+        RedisData::ListQuickList2(_, RedisList { items, .. }) => {
+            visitor.start_list(key, expire);
+            for node in items {
+                for value in &node.datas {
+                    visitor.rpush(key, value);
+                }
+            }
+            visitor.end_list(key);
+        }
+        RedisData::Set(_, RedisList { items, .. }) => {
+            visitor.start_set(key, items.len(), expire);
+            for LinkedListItem(member) in items {
+                visitor.sadd(key, &member.as_bytes());
+            }
+            visitor.end_set(key);
+        }
+        RedisData::SetIntSet(_, rs) => {
+            let IntSet { ints, .. } = IntSet::from_buf(&rs.as_bytes())?;
+            visitor.start_set(key, ints.len(), expire);
+            for int in ints {
+                visitor.sadd(key, format!("{}", int).as_bytes());
+            }
+            visitor.end_set(key);
+        }
+        RedisData::SetListPack(_, lp) => {
+            visitor.start_set(key, lp.datas.len(), expire);
+            for member in &lp.datas {
+                visitor.sadd(key, member);
+            }
+            visitor.end_set(key);
+        }
+        RedisData::ZSet(_, RedisList { items, .. }) => {
+            visitor.start_sorted_set(key, items.len(), expire);
+            for ZSetItem { member, score } in items {
+                let score = parse_score(&score.clone().into_data())?;
+                visitor.zadd(key, score, &member.as_bytes());
+            }
+            visitor.end_sorted_set(key);
+        }
+        RedisData::ZSet2(_, RedisList { items, .. }) => {
+            visitor.start_sorted_set(key, items.len(), expire);
+            for &ZSet2Item { ref member, score } in items {
+                visitor.zadd(key, score, &member.as_bytes());
+            }
+            visitor.end_sorted_set(key);
+        }
+        RedisData::ZSetZipList(_, rs) => {
+            let ZipList { entries, .. } = ZipList::from_buf(&rs.as_bytes())?;
+            let datas = entries.into_iter().map(|ZipListEntry { sp, .. }| sp.into_data());
+            visit_zset_pairs(key, &datas.collect::<Vec<_>>(), expire, visitor)?;
+        }
+        RedisData::ZSetListPack(_, lp) => {
+            visit_zset_pairs(key, &lp.datas, expire, visitor)?;
+        }
+        RedisData::Hash(_, RedisList { items, .. }) |
+        RedisData::HashMetadata(_, HashMetadata { items, .. }) => {
+            visitor.start_hash(key, items.len(), expire);
+            for item in items {
+                visitor.hset(key, &item.key.as_bytes(), &item.value.as_bytes(), item.expire);
+            }
+            visitor.end_hash(key);
+        }
+        RedisData::HashZipMap(_, zm) => {
+            visitor.start_hash(key, zm.entries.len(), expire);
+            for entry in &zm.entries {
+                visitor.hset(key, &entry.key, &entry.value, None);
+            }
+            visitor.end_hash(key);
+        }
+        RedisData::HashZipList(_, rs) => {
+            let ZipList { entries, .. } = ZipList::from_buf(&rs.as_bytes())?;
+            let datas = entries.into_iter().map(|ZipListEntry { sp, .. }| sp.into_data());
+            visit_hash_pairs(key, &datas.collect::<Vec<_>>(), expire, visitor)?;
+        }
+        RedisData::HashListPack(_, lp) => {
+            visit_hash_pairs(key, &lp.datas, expire, visitor)?;
+        }
+        RedisData::HashListPackEx(_, hash) => {
+            visitor.start_hash(key, hash.fields.len(), expire);
+            for &(ref field, ref value, field_expire) in &hash.fields {
+                visitor.hset(key, field, value, field_expire);
+            }
+            visitor.end_hash(key);
+        }
+        RedisData::Stream(_, stream) => {
+            visitor.stream(key, stream, expire);
+        }
+        RedisData::Module(_, module) => {
+            visitor.module(key, &module.name, module.value.as_ref(), expire);
+        }
+    }
+    Ok(())
+}
+
+// ziplist and listpack keep a hash as field, value, field, value...
+fn visit_hash_pairs<V: RdbVisitor>(key: &[u8],
+                                   datas: &[Vec<u8>],
+                                   expire: Option<u64>,
+                                   visitor: &mut V)
+                                   -> Result<()> {
+    faild!(datas.len() % 2 == 1, "hash field without a value");
+    visitor.start_hash(key, datas.len() / 2, expire);
+    for pair in datas.chunks(2) {
+        visitor.hset(key, &pair[0], &pair[1], None);
+    }
+    visitor.end_hash(key);
+    Ok(())
+}
+
+// and a sorted set as member, score, member, score...
+fn visit_zset_pairs<V: RdbVisitor>(key: &[u8],
+                                   datas: &[Vec<u8>],
+                                   expire: Option<u64>,
+                                   visitor: &mut V)
+                                   -> Result<()> {
+    faild!(datas.len() % 2 == 1, "sorted set member without a score");
+    visitor.start_sorted_set(key, datas.len() / 2, expire);
+    for pair in datas.chunks(2) {
+        visitor.zadd(key, parse_score(&pair[1])?, &pair[0]);
+    }
+    visitor.end_sorted_set(key);
+    Ok(())
+}
+
+pub fn parse_score(text: &[u8]) -> Result<f64> {
+    str::from_utf8(text)
+        .ok()
+        .and_then(|text| text.parse::<f64>().ok())
+        .ok_or(Error::Faild("sorted set score is not a number"))
+}
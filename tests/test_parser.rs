@@ -1,5 +1,8 @@
 extern crate libnewbee;
+use std::cell::Cell;
 use std::fs::File;
+use std::io::{self, Read};
+use std::rc::Rc;
 
 #[test]
 fn test_parser() {
@@ -10,3 +13,1655 @@ fn test_parser() {
         println!("cmd: {:?}", cmd);
     }
 }
+
+#[test]
+fn test_aux_metadata() {
+    let mut file = File::open("rdb/dump.rdb").unwrap();
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = dparser.read_to_cmd(&mut file).unwrap();
+    assert_eq!(parsed.len(), 10);
+
+    let meta = dparser.metadata();
+    assert_eq!(meta.version, Some(7));
+    assert_eq!(meta.redis_ver, Some("3.2.12".to_owned()));
+    assert_eq!(meta.redis_bits, Some(64));
+    assert_eq!(meta.ctime, Some(1500000000));
+    assert_eq!(meta.used_mem, Some(821344));
+    assert_eq!(meta.repl_id, None);
+    assert!(meta.others.is_empty());
+}
+
+fn rdb(version: &str, body: &[u8]) -> Vec<u8> {
+    let mut buf = b"REDIS".to_vec();
+    buf.extend_from_slice(version.as_bytes());
+    buf.extend_from_slice(body);
+    buf.push(0xff);
+    // the CRC64 footer exists since rdb version 5
+    if version >= "0005" {
+        buf.extend_from_slice(&[0; 8]);
+    }
+    buf
+}
+
+#[test]
+fn test_resize_db() {
+    let mut file = File::open("rdb/dump.rdb").unwrap();
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.read_to_cmd(&mut file).unwrap();
+    let dbs = dparser.databases();
+    assert_eq!(dbs.len(), 1);
+    assert_eq!(dbs[0].db, 0);
+    assert_eq!(dbs[0].declared_keys, Some(9));
+    assert_eq!(dbs[0].declared_expires, Some(1));
+    assert_eq!(dbs[0].keys, 9);
+    assert_eq!(dbs[0].expires, 1);
+    assert!(dbs[0].is_complete());
+
+    // RESIZEDB declares 3 keys but only one follows
+    let buf = rdb("0009", b"\xfe\x00\xfb\x03\x00\x00\x01k\x01v");
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = dparser.read_to_cmd(&mut &buf[..]).unwrap();
+    assert_eq!(parsed.len(), 1);
+    let dbs = dparser.databases();
+    assert_eq!(dbs[0].declared_keys, Some(3));
+    assert_eq!(dbs[0].keys, 1);
+    assert!(!dbs[0].is_complete());
+}
+
+fn to_lines(cmds: Vec<libnewbee::RedisCmd>) -> Vec<String> {
+    cmds.into_iter()
+        .map(|cmd| {
+            let args: Vec<String> = cmd.into_data()
+                .into_iter()
+                .map(|arg| String::from_utf8_lossy(&arg).into_owned())
+                .collect();
+            args.join(" ")
+        })
+        .collect()
+}
+
+#[test]
+fn test_multi_database() {
+    let buf = rdb("0009",
+                  b"\xfe\x00\xfb\x01\x00\x00\x01a\x011\
+                    \xfe\x03\xfb\x02\x00\x00\x01b\x012\x00\x01c\x013\
+                    \xfe\x00\x00\x01d\x014");
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed,
+               vec!["SET a 1", "SELECT 3", "SET b 2", "SET c 3", "SELECT 0", "SET d 4"]);
+
+    let dbs = dparser.databases();
+    assert_eq!(dbs.len(), 3);
+    assert_eq!((dbs[0].db, dbs[0].keys), (0, 1));
+    assert_eq!((dbs[1].db, dbs[1].keys), (3, 2));
+    assert_eq!((dbs[2].db, dbs[2].keys), (0, 1));
+    assert!(dbs.iter().all(|db| db.is_complete()));
+}
+
+fn rdb_len(len: usize) -> Vec<u8> {
+    if len < 64 {
+        vec![len as u8]
+    } else if len < 16384 {
+        vec![0x40 | (len >> 8) as u8, len as u8]
+    } else if len <= 0xffff_ffff {
+        let mut buf = vec![0x80];
+        buf.extend_from_slice(&be_bytes(len as u64)[4..]);
+        buf
+    } else {
+        let mut buf = vec![0x81];
+        buf.extend(be_bytes(len as u64));
+        buf
+    }
+}
+
+fn rdb_string(data: &[u8]) -> Vec<u8> {
+    let mut buf = rdb_len(data.len());
+    buf.extend_from_slice(data);
+    buf
+}
+
+#[test]
+fn test_hash_zipmap() {
+    let long_value = vec![b'v'; 300];
+    let mut zipmap = vec![0x02];
+    // "name" => "bee" with two free bytes after the value
+    zipmap.extend_from_slice(b"\x04name\x03\x02bee\x00\x00");
+    // "long" => 300 bytes value, length encoded as 254 + u32
+    zipmap.extend_from_slice(b"\x04long\xfe\x2c\x01\x00\x00\x00");
+    zipmap.extend_from_slice(&long_value);
+    zipmap.push(0xff);
+
+    let mut body = b"\xfe\x00\x09".to_vec();
+    body.extend(rdb_string(b"user:3"));
+    body.extend(rdb_string(&zipmap));
+    let buf = rdb("0003", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = dparser.read_to_cmd(&mut &buf[..]).unwrap();
+    assert_eq!(parsed.len(), 1);
+    let args = parsed.into_iter().next().unwrap().into_data();
+    assert_eq!(args,
+               vec![b"HSET".to_vec(),
+                    b"user:3".to_vec(),
+                    b"name".to_vec(),
+                    b"bee".to_vec(),
+                    b"long".to_vec(),
+                    long_value]);
+
+    // a zipmap without its end flag fails the parse
+    let mut body = b"\xfe\x00".to_vec();
+    body.extend(key_value(9, b"user:3", b"\x01\x01a\x01\x00b"));
+    let buf = rdb("0003", &body);
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    match dparser.read_to_cmd(&mut &buf[..]) {
+        Err(libnewbee::Error::Faild(_)) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+// ziplist string entries share the 6/14 bits length prefix of rdb strings
+fn ziplist(entries: &[Vec<u8>]) -> Vec<u8> {
+    let mut body = vec![];
+    let mut prev_len = 0;
+    let mut tail = 10;
+    for entry in entries {
+        tail = 10 + body.len();
+        body.push(prev_len as u8);
+        body.extend_from_slice(entry);
+        prev_len = entry.len() + 1;
+    }
+    let total = 10 + body.len() + 1;
+    let mut buf = vec![];
+    buf.extend_from_slice(&[total as u8, (total >> 8) as u8, 0, 0]);
+    buf.extend_from_slice(&[tail as u8, (tail >> 8) as u8, 0, 0]);
+    buf.extend_from_slice(&[entries.len() as u8, 0]);
+    buf.extend(body);
+    buf.push(0xff);
+    buf
+}
+
+fn lzf_literal(data: &[u8]) -> Vec<u8> {
+    let mut compressed = vec![];
+    for chunk in data.chunks(32) {
+        compressed.push(chunk.len() as u8 - 1);
+        compressed.extend_from_slice(chunk);
+    }
+    let mut buf = vec![0xc3];
+    buf.extend(rdb_len(compressed.len()));
+    buf.extend(rdb_len(data.len()));
+    buf.extend(compressed);
+    buf
+}
+
+#[test]
+fn test_list_quicklist() {
+    let long_item = vec![b'q'; 100];
+    let first = ziplist(&[rdb_string(b"a"), rdb_string(&long_item), vec![0xf0, 0x00, 0x00, 0x80]]);
+    let second = ziplist(&[rdb_string(b"d"), vec![0xf2], rdb_string(b"f")]);
+
+    let mut body = b"\xfe\x00\x0e".to_vec();
+    body.extend(rdb_string(b"jobs"));
+    body.push(0x02);
+    body.extend(rdb_string(&first));
+    body.extend(lzf_literal(&second));
+    let buf = rdb("0007", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = dparser.read_to_cmd(&mut &buf[..]).unwrap();
+    assert_eq!(parsed.len(), 1);
+    let args = parsed.into_iter().next().unwrap().into_data();
+    assert_eq!(args,
+               vec![b"RPUSH".to_vec(),
+                    b"jobs".to_vec(),
+                    b"a".to_vec(),
+                    long_item,
+                    b"-8388608".to_vec(),
+                    b"d".to_vec(),
+                    b"1".to_vec(),
+                    b"f".to_vec()]);
+
+    // a node cut short fails the parse, whole or streamed
+    let mut body = b"\xfe\x00\x0e".to_vec();
+    body.extend(rdb_string(b"jobs"));
+    body.push(0x01);
+    body.extend(rdb_string(&second[..second.len() - 2]));
+    let buf = rdb("0007", &body);
+    for chunk in &[0, 1] {
+        let mut dparser = libnewbee::DefaultRdbParser::default();
+        dparser.set_element_chunk(*chunk);
+        match dparser.read_to_cmd(&mut &buf[..]) {
+            Err(libnewbee::Error::Faild(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
+
+fn lp_str(data: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0x80 | data.len() as u8];
+    buf.extend_from_slice(data);
+    buf
+}
+
+// entries are encoded without their backlen, all of them shorter than 128 bytes
+fn listpack(entries: &[Vec<u8>]) -> Vec<u8> {
+    let mut body = vec![];
+    for entry in entries {
+        body.extend_from_slice(entry);
+        body.push(entry.len() as u8);
+    }
+    let total = 6 + body.len() + 1;
+    let mut buf = vec![total as u8, (total >> 8) as u8, 0, 0];
+    buf.extend_from_slice(&[entries.len() as u8, 0]);
+    buf.extend(body);
+    buf.push(0xff);
+    buf
+}
+
+fn key_value(rtype: u8, key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut buf = vec![rtype];
+    buf.extend(rdb_string(key));
+    buf.extend(rdb_string(value));
+    buf
+}
+
+#[test]
+fn test_listpack_types() {
+    let mut body = b"\xfe\x00".to_vec();
+    let hash = listpack(&[lp_str(b"name"), lp_str(b"bee"), lp_str(b"age"), vec![12]]);
+    body.extend(key_value(16, b"h", &hash));
+    let zset = listpack(&[lp_str(b"low"), vec![0xdf, 0x9c], lp_str(b"high"), lp_str(b"2.5")]);
+    body.extend(key_value(17, b"z", &zset));
+    let set = listpack(&[lp_str(b"x"), vec![0xf1, 0x10, 0x27]]);
+    body.extend(key_value(20, b"s", &set));
+
+    // quicklist v2: a PACKED listpack node then a PLAIN node
+    body.push(18);
+    body.extend(rdb_string(b"l"));
+    body.push(0x02);
+    body.push(0x02);
+    body.extend(rdb_string(&listpack(&[lp_str(b"a"), vec![0x07]])));
+    body.push(0x01);
+    body.extend(rdb_string(b"plain item"));
+
+    let zziplist = ziplist(&[rdb_string(b"m"), vec![0xf4]]);
+    body.extend(key_value(12, b"zz", &zziplist));
+    let buf = rdb("0011", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed,
+               vec!["HSET h name bee age 12",
+                    "ZADD z -100 low 2.5 high",
+                    "SADD s x 10000",
+                    "RPUSH l a 7 plain item",
+                    "ZADD zz 3 m"]);
+
+    // a corrupt listpack, or a hash one with a field but no value, fails the
+    // parse instead of panicking
+    let set = listpack(&[lp_str(b"x"), lp_str(b"y")]);
+    let hash = listpack(&[lp_str(b"x"), lp_str(b"y"), lp_str(b"z")]);
+    for &(rtype, corrupt) in &[(20, &set[..set.len() - 1]), (16, &hash[..])] {
+        let mut body = b"\xfe\x00".to_vec();
+        body.extend(key_value(rtype, b"k", corrupt));
+        let buf = rdb("0011", &body);
+        let mut dparser = libnewbee::DefaultRdbParser::default();
+        match dparser.read_to_cmd(&mut &buf[..]) {
+            Err(libnewbee::Error::Faild(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
+
+fn le_bytes(mut value: u64, len: usize) -> Vec<u8> {
+    let mut buf = vec![];
+    for _ in 0..len {
+        buf.push(value as u8);
+        value >>= 8;
+    }
+    buf
+}
+
+#[test]
+fn test_zset2_binary_scores() {
+    let scores = [(1.5, "1.5"),
+                  (f64::NEG_INFINITY, "-inf"),
+                  (f64::INFINITY, "inf"),
+                  (0.1, "0.1"),
+                  (3.0, "3"),
+                  (-0.0001, "-0.0001"),
+                  (1e20, "1e+20"),
+                  (1e-5, "1e-05"),
+                  (123456789012345680.0, "1.2345678901234568e+17")];
+    let mut body = b"\xfe\x00\x05".to_vec();
+    body.extend(rdb_string(b"scores"));
+    body.push(scores.len() as u8);
+    let mut expect = "ZADD scores".to_owned();
+    for (i, &(score, text)) in scores.iter().enumerate() {
+        let member = format!("m{}", i);
+        body.extend(rdb_string(member.as_bytes()));
+        body.extend(le_bytes(f64::to_bits(score), 8));
+        expect.push_str(&format!(" {} {}", text, member));
+    }
+    let buf = rdb("0009", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed, vec![expect]);
+}
+
+fn be_bytes(value: u64) -> Vec<u8> {
+    let mut buf = le_bytes(value, 8);
+    buf.reverse();
+    buf
+}
+
+fn stream_id(ms: u64, seq: u64) -> Vec<u8> {
+    let mut buf = be_bytes(ms);
+    buf.extend(be_bytes(seq));
+    buf
+}
+
+#[test]
+fn test_stream() {
+    let lp = listpack(&[// master entry: count, deleted, fields, terminator
+                        vec![2],
+                        vec![1],
+                        vec![2],
+                        lp_str(b"name"),
+                        lp_str(b"age"),
+                        vec![0],
+                        // 1-0 with the master fields
+                        vec![2],
+                        vec![0],
+                        vec![0],
+                        lp_str(b"bee"),
+                        vec![3],
+                        vec![5],
+                        // 2-0 deleted
+                        vec![3],
+                        vec![1],
+                        vec![0],
+                        lp_str(b"x"),
+                        lp_str(b"y"),
+                        vec![5],
+                        // 3-5 with its own fields
+                        vec![0],
+                        vec![2],
+                        vec![5],
+                        vec![1],
+                        lp_str(b"color"),
+                        lp_str(b"red"),
+                        vec![6]]);
+    let mut body = b"\xfe\x00\x13".to_vec();
+    body.extend(rdb_string(b"s"));
+    body.push(1);
+    body.extend(rdb_string(&stream_id(1, 0)));
+    body.extend(rdb_string(&lp));
+    // length, last id, first id, max deleted id, entries added
+    body.extend_from_slice(&[2, 3, 5, 1, 0, 2, 0, 3]);
+    // one group "g" at 1-0 which read one entry
+    body.push(1);
+    body.extend(rdb_string(b"g"));
+    body.extend_from_slice(&[1, 0, 1]);
+    body.push(1);
+    body.extend(stream_id(1, 0));
+    body.extend(le_bytes(1700000000000, 8));
+    body.push(2);
+    // consumers: alice owns 1-0, bob owns nothing
+    body.push(2);
+    body.extend(rdb_string(b"alice"));
+    body.extend(le_bytes(1700000000001, 8));
+    body.push(1);
+    body.extend(stream_id(1, 0));
+    body.extend(rdb_string(b"bob"));
+    body.extend(le_bytes(1700000000002, 8));
+    body.push(0);
+
+    // an empty v1 stream
+    body.push(0x0f);
+    body.extend(rdb_string(b"e"));
+    body.extend_from_slice(&[0, 0, 5, 1, 0]);
+    let buf = rdb("0010", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed,
+               vec!["XADD s 1-0 name bee age 3",
+                    "XADD s 3-5 color red",
+                    "XSETID s 3-5 ENTRIESADDED 3 MAXDELETEDID 2-0",
+                    "XGROUP CREATE s g 1-0 ENTRIESREAD 1",
+                    "XCLAIM s g alice 0 1-0 TIME 1700000000000 RETRYCOUNT 2 JUSTID FORCE",
+                    "XGROUP CREATECONSUMER s g bob",
+                    "XADD e MAXLEN 0 0-1 x y",
+                    "XSETID e 5-1"]);
+
+    // the ids of the groups, PELs and consumers may be split between feeds
+    for chunk in &[1, 3, 7] {
+        let mut dparser = libnewbee::DefaultRdbParser::default();
+        let mut fed = vec![];
+        for data in buf.chunks(*chunk) {
+            dparser.feed(data);
+            fed.extend(dparser.poll().unwrap());
+        }
+        assert!(dparser.is_finished());
+        assert_eq!(to_lines(fed), parsed);
+    }
+
+    // a master id shorter than 16 bytes is malformed, not a short read
+    let mut body = b"\xfe\x00\x13".to_vec();
+    body.extend(rdb_string(b"s"));
+    body.push(1);
+    body.extend(rdb_string(&stream_id(1, 0)[..8]));
+    body.extend(rdb_string(&lp));
+    body.extend_from_slice(&[2, 3, 5, 1, 0, 2, 0, 3, 0]);
+    let buf = rdb("0010", &body);
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    match dparser.read_to_cmd(&mut &buf[..]) {
+        Err(libnewbee::Error::Faild(_)) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_checksum() {
+    let mut buf = vec![];
+    File::open("rdb/dump.rdb").unwrap().read_to_end(&mut buf).unwrap();
+    let pos = buf.windows(5).position(|w| w == b"hello").unwrap();
+    buf[pos] = b'H';
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    match dparser.read_to_cmd(&mut &buf[..]) {
+        Err(libnewbee::Error::Checksum { expected, computed }) => assert!(expected != computed),
+        other => panic!("checksum mismatch not detected: {:?}", other),
+    }
+}
+
+#[test]
+fn test_large_lengths() {
+    let big_value = vec![b'b'; 70000];
+    let mut body = b"\xfe\x00".to_vec();
+    body.extend(key_value(0, b"big", &big_value));
+    body.push(0x02);
+    body.extend(rdb_string(b"members"));
+    body.extend(rdb_len(20000));
+    for i in 0..20000 {
+        body.extend(rdb_string(format!("{}", i).as_bytes()));
+    }
+    // an empty stream whose last id needs a 64 bits length
+    body.push(0x0f);
+    body.extend(rdb_string(b"events"));
+    body.extend_from_slice(&[0, 0]);
+    body.extend(rdb_len(1700000000000));
+    body.extend_from_slice(&[3, 0]);
+    let buf = rdb("0009", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = dparser.read_to_cmd(&mut &buf[..]).unwrap();
+    let mut parsed = parsed.into_iter().map(|cmd| cmd.into_data());
+    let set = parsed.next().unwrap();
+    assert_eq!(set.len(), 3);
+    assert_eq!(set[2], big_value);
+    let sadd = parsed.next().unwrap();
+    assert_eq!(sadd.len(), 2 + 20000);
+    assert_eq!(sadd[2 + 19999], b"19999".to_vec());
+    let xsetid: Vec<String> = parsed.nth(1)
+        .unwrap()
+        .into_iter()
+        .map(|arg| String::from_utf8(arg).unwrap())
+        .collect();
+    assert_eq!(xsetid, vec!["XSETID", "events", "1700000000000-3"]);
+}
+
+#[test]
+fn test_idle_and_freq() {
+    let mut body = b"\xfe\x00".to_vec();
+    body.extend_from_slice(b"\xf8\x50\x10");
+    body.extend(key_value(0, b"cold", b"c"));
+    body.extend_from_slice(b"\xf9\x05");
+    body.extend(key_value(0, b"hot", b"h"));
+    body.push(0xfc);
+    body.extend(le_bytes(4102444800000, 8));
+    body.extend_from_slice(b"\xf9\x07");
+    body.extend(key_value(0, b"ttl", b"t"));
+    body.extend(key_value(0, b"plain", b"p"));
+    let buf = rdb("0009", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed[..3].to_vec(), vec!["SET cold c", "SET hot h", "SET ttl t"]);
+    assert!(parsed[3].starts_with("EXPIRE ttl "));
+    assert_eq!(parsed[4], "SET plain p");
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.set_restore_hints(true);
+    let parsed: Vec<_> = dparser.read_to_cmd(&mut &buf[..])
+        .unwrap()
+        .into_iter()
+        .map(|cmd| cmd.into_data())
+        .collect();
+    assert_eq!(parsed.len(), 4);
+
+    let restore = &parsed[0];
+    assert_eq!(restore.len(), 7);
+    assert_eq!(restore[0], b"RESTORE".to_vec());
+    assert_eq!(restore[1], b"cold".to_vec());
+    assert_eq!(restore[2], b"0".to_vec());
+    // type, value, rdb version and crc64
+    assert_eq!(restore[3][..5].to_vec(), b"\x00\x01c\x09\x00".to_vec());
+    assert_eq!(restore[3].len(), 5 + 8);
+    assert_eq!(restore[4..].to_vec(),
+               vec![b"REPLACE".to_vec(), b"IDLETIME".to_vec(), b"4112".to_vec()]);
+
+    assert_eq!(parsed[1][4..].to_vec(),
+               vec![b"REPLACE".to_vec(), b"FREQ".to_vec(), b"5".to_vec()]);
+    assert_eq!(parsed[2][2], b"4102444800000".to_vec());
+    assert_eq!(parsed[2][4..].to_vec(),
+               vec![b"REPLACE".to_vec(), b"ABSTTL".to_vec(), b"FREQ".to_vec(), b"7".to_vec()]);
+    assert_eq!(parsed[3], vec![b"SET".to_vec(), b"plain".to_vec(), b"p".to_vec()]);
+    assert!(dparser.key_hints().is_empty());
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.set_collect_hints(true);
+    dparser.read_to_cmd(&mut &buf[..]).unwrap();
+    let hints: Vec<_> = dparser.key_hints()
+        .iter()
+        .map(|hint| (String::from_utf8(hint.key.clone()).unwrap(), hint.idle, hint.freq))
+        .collect();
+    assert_eq!(hints,
+               vec![("cold".to_owned(), Some(4112), None),
+                    ("hot".to_owned(), None, Some(5)),
+                    ("ttl".to_owned(), None, Some(7))]);
+}
+
+fn module_id(name: &str, encver: u64) -> u64 {
+    let charset = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut id = 0;
+    for c in name.bytes() {
+        id = id << 6 | charset.iter().position(|&x| x == c).unwrap() as u64;
+    }
+    id << 10 | encver
+}
+
+// RDB_TYPE_MODULE_2 value: module id, then opcode tagged fields up to EOF
+fn module_value(name: &str) -> Vec<u8> {
+    let mut value = rdb_len(module_id(name, 3) as usize);
+    value.push(2);
+    value.extend(rdb_len(42));
+    value.push(5);
+    value.extend(rdb_string(b"blob"));
+    value.push(4);
+    value.extend(le_bytes(2.5f64.to_bits(), 8));
+    value.push(0);
+    value
+}
+
+struct Counter;
+
+impl libnewbee::ModuleTypeHandler for Counter {
+    fn decode(&self,
+              key: &[u8],
+              encver: u32,
+              reader: &mut libnewbee::ModuleReader)
+              -> libnewbee::Result<libnewbee::ModuleValue> {
+        let count = reader.load_unsigned()?;
+        let name = reader.load_string()?;
+        let score = reader.load_double()?;
+        let value = format!("{}:{}:{}:{}", encver, count, String::from_utf8_lossy(&name), score);
+        Ok(libnewbee::ModuleValue::Commands(vec![vec![b"SET".to_vec(),
+                                                      key.to_vec(),
+                                                      value.into_bytes()]]))
+    }
+}
+
+struct Opaque;
+
+impl libnewbee::ModuleTypeHandler for Opaque {
+    fn decode(&self,
+              _key: &[u8],
+              _encver: u32,
+              _reader: &mut libnewbee::ModuleReader)
+              -> libnewbee::Result<libnewbee::ModuleValue> {
+        Ok(libnewbee::ModuleValue::Opaque)
+    }
+}
+
+#[test]
+fn test_module_types() {
+    let mut body = b"\xfe\x00".to_vec();
+    body.push(7);
+    body.extend(rdb_string(b"counter"));
+    body.extend(module_value("counter-1"));
+    body.push(0xfc);
+    body.extend(le_bytes(4102444800000, 8));
+    body.push(7);
+    body.extend(rdb_string(b"blob"));
+    body.extend(module_value("opaque-01"));
+    body.extend(key_value(0, b"after", b"a"));
+    let buf = rdb("0009", &body);
+
+    // without handlers module values are skipped and replayed by RESTORE
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed: Vec<_> = dparser.read_to_cmd(&mut &buf[..])
+        .unwrap()
+        .into_iter()
+        .map(|cmd| cmd.into_data())
+        .collect();
+    assert_eq!(parsed.len(), 3);
+    assert_eq!(parsed[0][..3].to_vec(),
+               vec![b"RESTORE".to_vec(), b"counter".to_vec(), b"0".to_vec()]);
+    let mut payload = vec![7];
+    payload.extend(module_value("counter-1"));
+    payload.extend_from_slice(b"\x09\x00");
+    assert_eq!(parsed[0][3][..payload.len()].to_vec(), payload);
+    assert_eq!(parsed[1][..3].to_vec(),
+               vec![b"RESTORE".to_vec(), b"blob".to_vec(), b"4102444800000".to_vec()]);
+    assert_eq!(parsed[2], vec![b"SET".to_vec(), b"after".to_vec(), b"a".to_vec()]);
+    assert_eq!(dparser.databases()[0].keys, 3);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.register_module("counter-1", Counter);
+    dparser.register_module("opaque-01", Opaque);
+    let parsed: Vec<_> = dparser.read_to_cmd(&mut &buf[..])
+        .unwrap()
+        .into_iter()
+        .map(|cmd| cmd.into_data())
+        .collect();
+    assert_eq!(parsed.len(), 3);
+    assert_eq!(parsed[0],
+               vec![b"SET".to_vec(), b"counter".to_vec(), b"3:42:blob:2.5".to_vec()]);
+
+    let restore = &parsed[1];
+    assert_eq!(restore[..3].to_vec(),
+               vec![b"RESTORE".to_vec(), b"blob".to_vec(), b"4102444800000".to_vec()]);
+    let mut payload = vec![7];
+    payload.extend(module_value("opaque-01"));
+    payload.extend_from_slice(b"\x09\x00");
+    assert_eq!(restore[3][..payload.len()].to_vec(), payload);
+    assert_eq!(restore[4..].to_vec(), vec![b"REPLACE".to_vec(), b"ABSTTL".to_vec()]);
+    assert_eq!(parsed[2], vec![b"SET".to_vec(), b"after".to_vec(), b"a".to_vec()]);
+}
+
+#[test]
+fn test_rejson() {
+    // encoding version 3 saves the document as JSON text
+    let mut text = rdb_len(module_id("ReJSON-RL", 3) as usize);
+    text.push(5);
+    let doc = r#"{"name": "bee", "tags": ["a\"b", "é\ud83d\ude00"], "n": 1.50}"#;
+    text.extend(rdb_string(doc.as_bytes()));
+    text.push(0);
+
+    // encoding version 0 saves the RedisJSON 1.x node tree
+    let mut tree = rdb_len(module_id("ReJSON-RL", 0) as usize);
+    let node = |buf: &mut Vec<u8>, node_type: usize| {
+        buf.push(2);
+        buf.extend(rdb_len(node_type));
+    };
+    node(&mut tree, 0x20);
+    tree.push(2);
+    tree.extend(rdb_len(3));
+    node(&mut tree, 0x80);
+    tree.push(5);
+    tree.extend(rdb_string(b"ok"));
+    node(&mut tree, 0x10);
+    tree.push(5);
+    tree.extend(rdb_string(b"1"));
+    node(&mut tree, 0x80);
+    tree.push(5);
+    tree.extend(rdb_string(b"list"));
+    node(&mut tree, 0x40);
+    tree.push(2);
+    tree.extend(rdb_len(4));
+    node(&mut tree, 0x08);
+    tree.push(1);
+    tree.extend(rdb_len(7));
+    node(&mut tree, 0x04);
+    tree.push(4);
+    tree.extend(le_bytes(3.0f64.to_bits(), 8));
+    node(&mut tree, 0x02);
+    tree.push(5);
+    tree.extend(rdb_string(b"s"));
+    node(&mut tree, 0x01);
+    node(&mut tree, 0x80);
+    tree.push(5);
+    tree.extend(rdb_string(b"empty"));
+    node(&mut tree, 0x20);
+    tree.push(2);
+    tree.extend(rdb_len(0));
+    tree.push(0);
+
+    let mut body = b"\xfe\x00".to_vec();
+    body.push(7);
+    body.extend(rdb_string(b"doc"));
+    body.extend(text);
+    body.push(7);
+    body.extend(rdb_string(b"old"));
+    body.extend(tree);
+    let buf = rdb("0009", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed,
+               vec![r#"JSON.SET doc $ {"name":"bee","tags":["a\"b","é😀"],"n":1.5}"#,
+                    r#"JSON.SET old $ {"ok":true,"list":[7,3.0,"s",null],"empty":{}}"#]);
+}
+
+#[test]
+fn test_json_value() {
+    use libnewbee::JsonValue;
+
+    let json = JsonValue::parse(b" [1, -2.5e3, true, null, \"\\n\\u0001\", {\"k\": []}] ").unwrap();
+    assert_eq!(json,
+               JsonValue::Array(vec![JsonValue::Int(1),
+                                     JsonValue::Number(-2500.0),
+                                     JsonValue::Bool(true),
+                                     JsonValue::Null,
+                                     JsonValue::String("\n\u{1}".to_owned()),
+                                     JsonValue::Object(vec![("k".to_owned(),
+                                                             JsonValue::Array(vec![]))])]));
+    assert_eq!(json.to_json(), r#"[1,-2500.0,true,null,"\n\u0001",{"k":[]}]"#);
+    assert!(JsonValue::parse(b"[1,").is_err());
+    assert!(JsonValue::parse(b"{} x").is_err());
+}
+
+#[test]
+fn test_functions() {
+    let library = b"#!lua name=mylib\nredis.register_function('f', function() return 1 end)";
+    let mut body = vec![0xf5];
+    body.extend(rdb_string(library));
+    // pre-GA format: name, engine, has description, description, code
+    body.push(0xf6);
+    body.extend(rdb_string(b"oldlib"));
+    body.extend(rdb_string(b"LUA"));
+    body.extend(rdb_len(1));
+    body.extend(rdb_string(b"an old library"));
+    body.extend(rdb_string(b"return 2"));
+    body.extend_from_slice(b"\xfe\x00");
+    body.extend(key_value(0, b"k", b"v"));
+    let buf = rdb("0010", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed: Vec<_> = dparser.read_to_cmd(&mut &buf[..])
+        .unwrap()
+        .into_iter()
+        .map(|cmd| cmd.into_data())
+        .collect();
+    let load = |source: &[u8]| {
+        vec![b"FUNCTION".to_vec(), b"LOAD".to_vec(), b"REPLACE".to_vec(), source.to_vec()]
+    };
+    assert_eq!(parsed,
+               vec![load(library),
+                    load(b"#!lua name=oldlib\nreturn 2"),
+                    vec![b"SET".to_vec(), b"k".to_vec(), b"v".to_vec()]]);
+}
+
+struct AuxCounter;
+
+impl libnewbee::ModuleTypeHandler for AuxCounter {
+    fn decode(&self,
+              _key: &[u8],
+              _encver: u32,
+              _reader: &mut libnewbee::ModuleReader)
+              -> libnewbee::Result<libnewbee::ModuleValue> {
+        Ok(libnewbee::ModuleValue::Opaque)
+    }
+
+    fn decode_aux(&self,
+                  _encver: u32,
+                  when: u64,
+                  reader: &mut libnewbee::ModuleReader)
+                  -> libnewbee::Result<libnewbee::ModuleValue> {
+        let count = reader.load_unsigned()?;
+        let value = format!("{}:{}", when, count).into_bytes();
+        Ok(libnewbee::ModuleValue::Commands(vec![vec![value]]))
+    }
+}
+
+#[test]
+fn test_module_aux_and_slot_info() {
+    let mut aux = vec![0xf7];
+    aux.extend(rdb_len(module_id("counter-1", 1) as usize));
+    aux.push(2);
+    aux.extend(rdb_len(2));
+    aux.push(2);
+    aux.extend(rdb_len(42));
+    aux.push(0);
+
+    let mut body = b"\xfe\x00".to_vec();
+    // slot id, slot size, expires slot size
+    body.push(0xf4);
+    body.extend(rdb_len(866));
+    body.extend(rdb_len(1));
+    body.extend(rdb_len(0));
+    body.extend(key_value(0, b"k", b"v"));
+    body.extend(aux);
+    let buf = rdb("0011", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed, vec!["SET k v"]);
+    assert_eq!(dparser.slots().to_vec(),
+               vec![libnewbee::SlotInfo {
+                        slot: 866,
+                        size: 1,
+                        expires_size: 0,
+                    }]);
+    let aux = &dparser.module_aux()[0];
+    assert_eq!((&aux.name[..], aux.encver, aux.when), ("counter-1", 1, 2));
+    assert_eq!(aux.value, None);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.register_module("counter-1", AuxCounter);
+    dparser.read_to_cmd(&mut &buf[..]).unwrap();
+    assert_eq!(dparser.module_aux()[0].value,
+               Some(libnewbee::ModuleValue::Commands(vec![vec![b"2:42".to_vec()]])));
+}
+
+#[test]
+fn test_hash_field_ttl() {
+    let min_expire = 4102444800000;
+    let hpexpireat = |key: &str, ms: u64, field: &str| {
+        format!("HPEXPIREAT {} {} FIELDS 1 {}", key, ms, field)
+    };
+    let mut body = b"\xfe\x00".to_vec();
+
+    // HASH_METADATA: min expire, then every field behind its relative TTL
+    body.push(24);
+    body.extend(rdb_string(b"meta"));
+    body.extend(le_bytes(min_expire, 8));
+    body.extend(rdb_len(2));
+    body.extend(rdb_len(1));
+    body.extend(rdb_string(b"f1"));
+    body.extend(rdb_string(b"v1"));
+    body.extend(rdb_len(0));
+    body.extend(rdb_string(b"f2"));
+    body.extend(rdb_string(b"v2"));
+
+    // pre-GA HASH_METADATA saves absolute TTLs
+    body.push(22);
+    body.extend(rdb_string(b"oldmeta"));
+    body.extend(rdb_len(1));
+    body.extend(rdb_len(min_expire as usize));
+    body.extend(rdb_string(b"f"));
+    body.extend(rdb_string(b"v"));
+
+    // HASH_LISTPACK_EX: min expire, then field, value, TTL triplets
+    let mut ttl = vec![0xf4];
+    ttl.extend(le_bytes(min_expire + 5, 8));
+    let lp = listpack(&[lp_str(b"a"), lp_str(b"1"), vec![0], lp_str(b"b"), lp_str(b"2"), ttl]);
+    body.push(25);
+    body.extend(rdb_string(b"lp"));
+    body.extend(le_bytes(min_expire, 8));
+    body.extend(rdb_string(&lp));
+
+    let lp = listpack(&[lp_str(b"c"), lp_str(b"3"), vec![100]]);
+    body.extend(key_value(23, b"oldlp", &lp));
+    body.extend(key_value(0, b"k", b"v"));
+    let buf = rdb("0012", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed,
+               vec!["HSET meta f1 v1 f2 v2".to_owned(),
+                    hpexpireat("meta", min_expire, "f1"),
+                    "HSET oldmeta f v".to_owned(),
+                    hpexpireat("oldmeta", min_expire, "f"),
+                    "HSET lp a 1 b 2".to_owned(),
+                    hpexpireat("lp", min_expire + 5, "b"),
+                    "HSET oldlp c 3".to_owned(),
+                    hpexpireat("oldlp", 100, "c"),
+                    "SET k v".to_owned()]);
+
+    // a TTL past the end of time once added to the min expire
+    let mut body = b"\xfe\x00".to_vec();
+    body.push(24);
+    body.extend(rdb_string(b"meta"));
+    body.extend(le_bytes(u64::MAX, 8));
+    body.extend(rdb_len(1));
+    body.extend(rdb_len(2));
+    body.extend(rdb_string(b"f"));
+    body.extend(rdb_string(b"v"));
+    let buf = rdb("0012", &body);
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    match dparser.read_to_cmd(&mut &buf[..]) {
+        Err(libnewbee::Error::Faild(_)) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_versions() {
+    use libnewbee::Error;
+
+    let parse = |buf: Vec<u8>| libnewbee::DefaultRdbParser::default().read_to_cmd(&mut &buf[..]);
+
+    for version in &["0001", "0006", "0011", "0012"] {
+        let buf = rdb(version, b"\xfe\x00\x00\x01k\x01v");
+        assert_eq!(to_lines(parse(buf).unwrap()), vec!["SET k v"]);
+    }
+    for &(version, number) in &[("0000", 0), ("0013", 13), ("0080", 80)] {
+        match parse(rdb(version, b"")) {
+            Err(Error::UnsupportedVersion(v)) => assert_eq!(v, number),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    match parse(b"VALKEY080\xff".to_vec()) {
+        Err(Error::Faild(_)) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // a stream in a redis 4.0 dump, an LFU hint in a redis 4.0 dump, and a
+    // type id nobody ever assigned
+    let bodies: Vec<(&[u8], u8)> = vec![(b"\xfe\x00\x0f\x01k\x00", 15),
+                                        (b"\xfe\x00\xf9\x05\x00\x01k\x01v", 0xf9),
+                                        (b"\xfe\x00\x08\x01k\x01v", 8)];
+    for (body, code) in bodies {
+        match parse(rdb("0008", body)) {
+            Err(Error::UnsupportedType { code: c, version: 8 }) => assert_eq!(c, code),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    // ziplist hashes came with redis 2.6 and rdb version 6
+    match parse(rdb("0005", b"\xfe\x00\x0d\x01k\x00")) {
+        Err(Error::UnsupportedType { code: 13, version: 5 }) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+fn parse_file(path: &str) -> Vec<String> {
+    let mut file = File::open(path).unwrap();
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    without_ttl(to_lines(dparser.read_to_cmd(&mut file).unwrap()))
+}
+
+// the EXPIRE ttl depends on the current time
+fn without_ttl(lines: Vec<String>) -> Vec<String> {
+    lines.into_iter()
+        .map(|line| if line.starts_with("EXPIRE ") {
+            line.rsplit_once(' ').unwrap().0.to_owned()
+        } else {
+            line
+        })
+        .collect()
+}
+
+#[test]
+fn test_legacy_v1() {
+    assert_eq!(parse_file("rdb/legacy_v1.rdb"),
+               vec!["SET greeting hello",
+                    "SET session 1024",
+                    "EXPIRE session",
+                    "RPUSH queue a b",
+                    "SADD tags red",
+                    "ZADD board 1.5 alice inf top -inf bottom",
+                    "HSET user:1 name bee"]);
+}
+
+#[test]
+fn test_legacy_v2() {
+    assert_eq!(parse_file("rdb/legacy_v2.rdb"),
+               vec!["HSET user:2 name bee age 12",
+                    "RPUSH numbers x 7 300 70000 1099511627776",
+                    "SADD ids 1 5 70000",
+                    "SET session s",
+                    "EXPIRE session",
+                    "SELECT 1",
+                    "SET other db1"]);
+}
+
+#[test]
+fn test_legacy_v3() {
+    assert_eq!(parse_file("rdb/legacy_v3.rdb"),
+               vec!["SET session s", "EXPIRE session", "ZADD board -2.25 alice"]);
+}
+
+#[test]
+fn test_legacy_v4() {
+    assert_eq!(parse_file("rdb/legacy_v4.rdb"),
+               vec!["ZADD board 2 alice 3.5 bob", "HSET user:3 name bee age 12"]);
+
+    // nothing is read behind the EOF opcode before the checksum was added
+    let mut buf = vec![];
+    File::open("rdb/legacy_v4.rdb").unwrap().read_to_end(&mut buf).unwrap();
+    buf.extend_from_slice(b"\x01\x02\x03\x04\x05\x06\x07\x08\x09");
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    assert_eq!(dparser.read_to_cmd(&mut &buf[..]).unwrap().len(), 2);
+}
+
+#[test]
+fn test_legacy_v5() {
+    assert_eq!(parse_file("rdb/legacy_v5.rdb"),
+               vec!["SET greeting hello", "SADD ids -3 4"]);
+
+    let mut buf = vec![];
+    File::open("rdb/legacy_v5.rdb").unwrap().read_to_end(&mut buf).unwrap();
+    let last = buf.len() - 1;
+    buf[last] ^= 0xff;
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    match dparser.read_to_cmd(&mut &buf[..]) {
+        Err(libnewbee::Error::Checksum { .. }) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+// hands out one byte per read
+struct Trickle<'a>(&'a [u8]);
+
+impl<'a> Read for Trickle<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.0.is_empty() || buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = self.0[0];
+        self.0 = &self.0[1..];
+        Ok(1)
+    }
+}
+
+// hands over its bytes, then would block instead of ending
+struct Blocking<'a>(&'a [u8]);
+
+impl<'a> Read for Blocking<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.0.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "no more yet"));
+        }
+        let len = self.0.read(buf)?;
+        Ok(len)
+    }
+}
+
+// a dump of `header` then `items` items, each generated while it is read
+struct Generated {
+    header: Vec<u8>,
+    items: usize,
+    item: fn(usize) -> Vec<u8>,
+    next: usize,
+    pending: Vec<u8>,
+    produced: Rc<Cell<usize>>,
+}
+
+impl Generated {
+    fn new(header: Vec<u8>,
+           items: usize,
+           item: fn(usize) -> Vec<u8>,
+           produced: Rc<Cell<usize>>)
+           -> Generated {
+        Generated {
+            header,
+            items,
+            item,
+            next: 0,
+            pending: vec![],
+            produced,
+        }
+    }
+}
+
+impl Read for Generated {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            if self.next == 0 {
+                self.pending = self.header.clone();
+            } else if self.next <= self.items {
+                self.pending = (self.item)(self.next);
+            } else if self.next == self.items + 1 {
+                self.pending = vec![0xff, 0, 0, 0, 0, 0, 0, 0, 0];
+            } else {
+                return Ok(0);
+            }
+            self.next += 1;
+        }
+        let len = std::cmp::min(buf.len(), self.pending.len());
+        buf[..len].copy_from_slice(&self.pending[..len]);
+        self.pending.drain(..len);
+        self.produced.set(self.produced.get() + len);
+        Ok(len)
+    }
+}
+
+#[test]
+fn test_incremental_reading() {
+    let mut whole = vec![];
+    File::open("rdb/dump.rdb").unwrap().read_to_end(&mut whole).unwrap();
+    let expected = without_ttl(to_lines(libnewbee::DefaultRdbParser::default()
+        .read_to_cmd(&mut &whole[..])
+        .unwrap()));
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = without_ttl(to_lines(dparser.read_to_cmd(&mut Trickle(&whole)).unwrap()));
+    assert_eq!(parsed, expected);
+
+    // a value larger than a read chunk, trickled in
+    let mut body = b"\xfe\x00".to_vec();
+    body.extend(key_value(0, b"big", &vec![b'b'; 200000]));
+    let buf = rdb("0009", &body);
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = dparser.read_to_cmd(&mut Trickle(&buf)).unwrap();
+    assert_eq!(parsed[0].clone().into_data()[2].len(), 200000);
+
+    // a truncated dump resumes once the rest comes in
+    let (head, tail) = whole.split_at(whole.len() / 2);
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let mut parsed = vec![];
+    match dparser.for_each_cmd(&mut &head[..], |cmd| parsed.push(cmd)) {
+        Err(libnewbee::Error::More) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(!dparser.is_finished());
+    assert!(parsed.len() < expected.len());
+    dparser.for_each_cmd(&mut &tail[..], |cmd| parsed.push(cmd)).unwrap();
+    assert!(dparser.is_finished());
+    assert_eq!(without_ttl(to_lines(parsed)), expected);
+
+    // a source that would block resumes as well
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let mut parsed = dparser.read_to_cmd(&mut Blocking(head)).unwrap();
+    assert!(!dparser.is_finished());
+    parsed.extend(dparser.read_to_cmd(&mut Blocking(tail)).unwrap());
+    assert!(dparser.is_finished());
+    assert_eq!(without_ttl(to_lines(parsed)), expected);
+}
+
+#[test]
+fn test_feed_and_poll() {
+    let mut whole = vec![];
+    File::open("rdb/dump.rdb").unwrap().read_to_end(&mut whole).unwrap();
+    let expected = without_ttl(to_lines(libnewbee::DefaultRdbParser::default()
+        .read_to_cmd(&mut &whole[..])
+        .unwrap()));
+
+    for chunk in &[1, 3, 7, 64, 1000] {
+        let mut dparser = libnewbee::DefaultRdbParser::default();
+        let mut parsed = vec![];
+        for data in whole.chunks(*chunk) {
+            assert!(!dparser.is_finished());
+            dparser.feed(data);
+            parsed.extend(dparser.poll().unwrap());
+        }
+        assert!(dparser.is_finished());
+        assert_eq!(without_ttl(to_lines(parsed)), expected);
+        assert!(dparser.poll().unwrap().is_empty());
+    }
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.feed(b"REDIS0009\xfe");
+    assert!(dparser.poll().unwrap().is_empty());
+    dparser.feed(b"\x00\x00\x01k");
+    assert!(dparser.poll().unwrap().is_empty());
+    dparser.feed(b"\x01v\xff");
+    assert_eq!(to_lines(dparser.poll().unwrap()), vec!["SET k v"]);
+    assert!(!dparser.is_finished());
+    dparser.feed(&[0; 8]);
+    assert!(dparser.poll().unwrap().is_empty());
+    assert!(dparser.is_finished());
+}
+
+#[test]
+fn test_streaming_bounded() {
+    let produced = Rc::new(Cell::new(0));
+    let mut source = Generated::new(b"REDIS0009\xfe\x00".to_vec(),
+                                    200000,
+                                    |i| key_value(0, format!("key:{}", i).as_bytes(), b"v"),
+                                    produced.clone());
+
+    // commands come out while the dump is still being produced
+    let mut count = 0;
+    let mut produced_at_first = None;
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.for_each_cmd(&mut source, |_| {
+            count += 1;
+            if produced_at_first.is_none() {
+                produced_at_first = Some(produced.get());
+            }
+        })
+        .unwrap();
+    assert_eq!(count, 200000);
+    assert!(produced_at_first.unwrap() < produced.get() / 10);
+    assert_eq!(dparser.databases()[0].keys, 200000);
+}
+
+#[test]
+fn test_entries() {
+    use libnewbee::{Entry, Value};
+
+    let mut body = b"\xfa".to_vec();
+    body.extend(rdb_string(b"redis-ver"));
+    body.extend(rdb_string(b"7.0.0"));
+    body.extend_from_slice(b"\xfe\x02\xfb\x03\x01");
+    body.extend_from_slice(b"\xf8\x50\x10");
+    body.extend(key_value(0, b"cold", b"c"));
+    body.push(0xfc);
+    body.extend(le_bytes(4102444800000, 8));
+    let zset = listpack(&[lp_str(b"low"), vec![0xdf, 0x9c], lp_str(b"high"), lp_str(b"2.5")]);
+    body.extend(key_value(17, b"z", &zset));
+    let hash = listpack(&[lp_str(b"name"), lp_str(b"bee"), lp_str(b"age"), vec![12]]);
+    body.extend(key_value(16, b"h", &hash));
+    let buf = rdb("0011", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let entries: Vec<_> = dparser.entries(&mut &buf[..]).map(|entry| entry.unwrap()).collect();
+    assert!(dparser.is_finished());
+    assert_eq!(entries.len(), 7);
+    match entries[0] {
+        Entry::Version(11) => {}
+        ref other => panic!("unexpected {:?}", other),
+    }
+    match entries[1] {
+        Entry::Aux { ref key, ref value } => {
+            assert_eq!(key, b"redis-ver");
+            assert_eq!(value, b"7.0.0");
+        }
+        ref other => panic!("unexpected {:?}", other),
+    }
+    match entries[2] {
+        Entry::SelectDb(2) => {}
+        ref other => panic!("unexpected {:?}", other),
+    }
+    match entries[3] {
+        Entry::ResizeDb { db_size: 3, expires_size: 1 } => {}
+        ref other => panic!("unexpected {:?}", other),
+    }
+
+    let keys: Vec<_> = entries.into_iter()
+        .filter_map(|entry| match entry {
+            Entry::Key(key) => Some(key),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(keys[0].db, 2);
+    assert_eq!(keys[0].key, b"cold");
+    assert_eq!(keys[0].idle, Some(4112));
+    assert_eq!(keys[0].expire, None);
+    match keys[0].value {
+        Value::String(ref value) => assert_eq!(value, b"c"),
+        ref other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(keys[1].expire, Some(4102444800000));
+    match keys[1].value {
+        Value::ZSet(ref pairs) => {
+            assert_eq!(pairs, &vec![(b"low".to_vec(), -100.0), (b"high".to_vec(), 2.5)])
+        }
+        ref other => panic!("unexpected {:?}", other),
+    }
+    match keys[2].value {
+        Value::Hash(ref fields) => {
+            assert_eq!(fields,
+                       &vec![(b"name".to_vec(), b"bee".to_vec(), None),
+                             (b"age".to_vec(), b"12".to_vec(), None)])
+        }
+        ref other => panic!("unexpected {:?}", other),
+    }
+
+    // a truncated dump ends with More
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let entries: Vec<_> = dparser.entries(&mut &buf[..buf.len() - 12]).collect();
+    match entries.last() {
+        Some(&Err(libnewbee::Error::More)) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(!dparser.is_finished());
+
+    // a listpack hash with an odd number of items is rejected
+    let mut body = b"\xfe\x00".to_vec();
+    body.extend(key_value(16, b"h", &listpack(&[lp_str(b"a"), lp_str(b"1"), lp_str(b"b")])));
+    let buf = rdb("0011", &body);
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    assert!(dparser.entries(&mut &buf[..])
+        .any(|entry| matches!(entry, Err(libnewbee::Error::Faild(_)))));
+
+    // every key of the sample dump decodes
+    let mut file = File::open("rdb/dump.rdb").unwrap();
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let keys = dparser.entries(&mut file)
+        .map(|entry| entry.unwrap())
+        .filter(|entry| matches!(*entry, Entry::Key(_)))
+        .count();
+    let parsed: usize = dparser.databases().iter().map(|stats| stats.keys).sum();
+    assert_eq!(keys, parsed);
+    assert!(keys > 0);
+}
+
+// records every callback as a line
+#[derive(Default)]
+struct Recorder {
+    events: Vec<String>,
+}
+
+fn text(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).into_owned()
+}
+
+impl libnewbee::RdbVisitor for Recorder {
+    fn start_rdb(&mut self, version: u32) {
+        self.events.push(format!("start_rdb {}", version));
+    }
+    fn aux_field(&mut self, key: &[u8], value: &[u8]) {
+        self.events.push(format!("aux {} {}", text(key), text(value)));
+    }
+    fn start_database(&mut self, db: usize) {
+        self.events.push(format!("start_database {}", db));
+    }
+    fn set(&mut self, key: &[u8], value: &[u8], expire: Option<u64>) {
+        self.events.push(format!("set {} {} {:?}", text(key), text(value), expire));
+    }
+    fn start_hash(&mut self, key: &[u8], length: usize, _expire: Option<u64>) {
+        self.events.push(format!("start_hash {} {}", text(key), length));
+    }
+    fn hset(&mut self, _key: &[u8], field: &[u8], value: &[u8], _field_expire: Option<u64>) {
+        self.events.push(format!("hset {} {}", text(field), text(value)));
+    }
+    fn end_hash(&mut self, key: &[u8]) {
+        self.events.push(format!("end_hash {}", text(key)));
+    }
+    fn start_list(&mut self, key: &[u8], _expire: Option<u64>) {
+        self.events.push(format!("start_list {}", text(key)));
+    }
+    fn rpush(&mut self, _key: &[u8], value: &[u8]) {
+        self.events.push(format!("rpush {}", text(value)));
+    }
+    fn end_list(&mut self, key: &[u8]) {
+        self.events.push(format!("end_list {}", text(key)));
+    }
+    fn start_set(&mut self, key: &[u8], cardinality: usize, _expire: Option<u64>) {
+        self.events.push(format!("start_set {} {}", text(key), cardinality));
+    }
+    fn sadd(&mut self, _key: &[u8], member: &[u8]) {
+        self.events.push(format!("sadd {}", text(member)));
+    }
+    fn start_sorted_set(&mut self, key: &[u8], length: usize, _expire: Option<u64>) {
+        self.events.push(format!("start_sorted_set {} {}", text(key), length));
+    }
+    fn zadd(&mut self, _key: &[u8], score: f64, member: &[u8]) {
+        self.events.push(format!("zadd {} {}", score, text(member)));
+    }
+    fn end_database(&mut self, db: usize) {
+        self.events.push(format!("end_database {}", db));
+    }
+    fn end_rdb(&mut self) {
+        self.events.push("end_rdb".to_owned());
+    }
+}
+
+#[test]
+fn test_visitor() {
+    let mut body = b"\xfe\x01".to_vec();
+    body.push(0xfc);
+    body.extend(le_bytes(4102444800000, 8));
+    body.extend(key_value(0, b"k", b"v"));
+    let hash = listpack(&[lp_str(b"name"), lp_str(b"bee"), lp_str(b"age"), vec![12]]);
+    body.extend(key_value(16, b"h", &hash));
+    let zset = listpack(&[lp_str(b"low"), vec![0xdf, 0x9c], lp_str(b"high"), lp_str(b"2.5")]);
+    body.extend(key_value(17, b"z", &zset));
+    let set = listpack(&[lp_str(b"x"), vec![0xf1, 0x10, 0x27]]);
+    body.extend(key_value(20, b"s", &set));
+    body.extend_from_slice(b"\xfe\x03");
+    body.push(18);
+    body.extend(rdb_string(b"l"));
+    body.push(0x02);
+    body.push(0x02);
+    body.extend(rdb_string(&listpack(&[lp_str(b"a"), vec![0x07]])));
+    body.push(0x01);
+    body.extend(rdb_string(b"plain item"));
+    let buf = rdb("0011", &body);
+
+    let mut recorder = Recorder::default();
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.visit(&mut &buf[..], &mut recorder).unwrap();
+    assert_eq!(recorder.events,
+               vec!["start_rdb 11",
+                    "start_database 1",
+                    "set k v Some(4102444800000)",
+                    "start_hash h 2",
+                    "hset name bee",
+                    "hset age 12",
+                    "end_hash h",
+                    "start_sorted_set z 2",
+                    "zadd -100 low",
+                    "zadd 2.5 high",
+                    "start_set s 2",
+                    "sadd x",
+                    "sadd 10000",
+                    "end_database 1",
+                    "start_database 3",
+                    "start_list l",
+                    "rpush a",
+                    "rpush 7",
+                    "rpush plain item",
+                    "end_list l",
+                    "end_database 3",
+                    "end_rdb"]);
+
+    // resumes when the rest of the dump comes in
+    let (head, tail) = buf.split_at(buf.len() / 2);
+    let mut recorder = Recorder::default();
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    match dparser.visit(&mut &head[..], &mut recorder) {
+        Err(libnewbee::Error::More) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(!dparser.is_finished());
+    assert!(recorder.events.last().unwrap() != "end_rdb");
+    dparser.visit(&mut &tail[..], &mut recorder).unwrap();
+    assert_eq!(recorder.events.len(), 22);
+    assert_eq!(recorder.events.last().unwrap(), "end_rdb");
+
+    // a listpack hash or sorted set with an odd number of items is rejected
+    for &(rtype, key) in &[(16, &b"h"[..]), (17, &b"z"[..])] {
+        let mut body = b"\xfe\x00".to_vec();
+        let odd = listpack(&[lp_str(b"a"), lp_str(b"1"), lp_str(b"b")]);
+        body.extend(key_value(rtype, key, &odd));
+        let buf = rdb("0011", &body);
+        let mut dparser = libnewbee::DefaultRdbParser::default();
+        match dparser.visit(&mut &buf[..], &mut Recorder::default()) {
+            Err(libnewbee::Error::Faild(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_element_chunks() {
+    use libnewbee::{Entry, Value};
+
+    let mut whole = vec![];
+    File::open("rdb/dump.rdb").unwrap().read_to_end(&mut whole).unwrap();
+    let expected = without_ttl(to_lines(libnewbee::DefaultRdbParser::default()
+        .read_to_cmd(&mut &whole[..])
+        .unwrap()));
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.set_element_chunk(1000);
+    let parsed = without_ttl(to_lines(dparser.read_to_cmd(&mut Trickle(&whole)).unwrap()));
+    assert_eq!(parsed, expected);
+
+    let mut body = b"\xfe\x00".to_vec();
+    body.push(0xfc);
+    body.extend(le_bytes(4102444800000, 8));
+    body.push(1);
+    body.extend(rdb_string(b"l"));
+    body.extend(rdb_len(5));
+    for value in &[b"a", b"b", b"c", b"d", b"e"] {
+        body.extend(rdb_string(*value));
+    }
+    body.push(5);
+    body.extend(rdb_string(b"z"));
+    body.extend(rdb_len(3));
+    for &(member, score) in &[(b"x", 1.5f64), (b"y", 2.0), (b"w", -3.0)] {
+        body.extend(rdb_string(member));
+        body.extend(le_bytes(score.to_bits(), 8));
+    }
+    body.extend(key_value(0, b"s", b"v"));
+    let buf = rdb("0009", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.set_element_chunk(2);
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed[..3].to_vec(), vec!["RPUSH l a b", "RPUSH l c d", "RPUSH l e"]);
+    assert!(parsed[3].starts_with("EXPIRE l "));
+    assert_eq!(parsed[4..].to_vec(), vec!["ZADD z 1.5 x 2 y", "ZADD z -3 w", "SET s v"]);
+    assert_eq!(dparser.databases()[0].keys, 3);
+    assert_eq!(dparser.databases()[0].expires, 1);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.set_element_chunk(2);
+    let chunks: Vec<_> = dparser.entries(&mut &buf[..])
+        .filter_map(|entry| match entry.unwrap() {
+            Entry::Chunk(chunk) => Some(chunk),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(chunks.len(), 5);
+    assert_eq!(chunks[2].key, b"l");
+    assert_eq!(chunks[2].expire, Some(4102444800000));
+    match chunks[2].value {
+        Value::List(ref values) => assert_eq!(values, &vec![b"e".to_vec()]),
+        ref other => panic!("unexpected {:?}", other),
+    }
+
+    let mut whole = Recorder::default();
+    libnewbee::DefaultRdbParser::default().visit(&mut &buf[..], &mut whole).unwrap();
+    let mut chunked = Recorder::default();
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.set_element_chunk(2);
+    dparser.visit(&mut &buf[..], &mut chunked).unwrap();
+    assert_eq!(chunked.events, whole.events);
+
+    // an empty collection rebuilds no key, so there is nothing to expire
+    let mut body = b"\xfe\x00".to_vec();
+    body.push(0xfc);
+    body.extend(le_bytes(4102444800000, 8));
+    body.push(2);
+    body.extend(rdb_string(b"empty"));
+    body.extend(rdb_len(0));
+    body.extend(key_value(0, b"s", b"v"));
+    let buf = rdb("0009", &body);
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.set_element_chunk(2);
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed, vec!["SET s v"]);
+
+    // the members of a huge set come out while it is still being produced
+    let produced = Rc::new(Cell::new(0));
+    let mut header = b"REDIS0009\xfe\x00\x02".to_vec();
+    header.extend(rdb_string(b"big"));
+    header.extend(rdb_len(200000));
+    let mut source = Generated::new(header,
+                                    200000,
+                                    |i| rdb_string(format!("member:{}", i).as_bytes()),
+                                    produced.clone());
+    let mut count = 0;
+    let mut members = 0;
+    let mut produced_at_first = None;
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.set_element_chunk(1000);
+    dparser.for_each_cmd(&mut source, |cmd| {
+            count += 1;
+            members += cmd.into_data().len() - 2;
+            if produced_at_first.is_none() {
+                produced_at_first = Some(produced.get());
+            }
+        })
+        .unwrap();
+    assert_eq!(count, 200);
+    assert_eq!(members, 200000);
+    assert!(produced_at_first.unwrap() < produced.get() / 10);
+}
+
+#[test]
+fn test_split_commands() {
+    let mut body = b"\xfe\x00".to_vec();
+    body.push(2);
+    body.extend(rdb_string(b"s"));
+    body.extend(rdb_len(5));
+    for member in &[b"a", b"b", b"c", b"d", b"e"] {
+        body.extend(rdb_string(*member));
+    }
+    let zset = listpack(&[lp_str(b"low"), vec![0xdf, 0x9c], lp_str(b"high"), lp_str(b"2.5"),
+                          lp_str(b"top"), vec![0x09]]);
+    body.extend(key_value(17, b"z", &zset));
+    let hash = listpack(&[lp_str(b"name"), lp_str(b"bee"), lp_str(b"age"), vec![12]]);
+    body.extend(key_value(16, b"h", &hash));
+    body.push(18);
+    body.extend(rdb_string(b"l"));
+    body.push(0x01);
+    body.push(0x02);
+    body.extend(rdb_string(&listpack(&[lp_str(b"a"), lp_str(b"b"), lp_str(b"c")])));
+    body.extend(key_value(0, b"k", b"v"));
+    let buf = rdb("0011", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.set_max_elements_per_cmd(2);
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed,
+               vec!["SADD s a b",
+                    "SADD s c d",
+                    "SADD s e",
+                    "ZADD z -100 low 2.5 high",
+                    "ZADD z 9 top",
+                    "HSET h name bee age 12",
+                    "RPUSH l a b",
+                    "RPUSH l c",
+                    "SET k v"]);
+
+    // "ZADD" "z" and one pair take 10 to 12 bytes
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.set_max_bytes_per_cmd(12);
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed[..4].to_vec(),
+               vec!["SADD s a b c d e", "ZADD z -100 low", "ZADD z 2.5 high", "ZADD z 9 top"]);
+    assert_eq!(parsed[4..6].to_vec(), vec!["HSET h name bee", "HSET h age 12"]);
+
+    // an element larger than the limit still gets a command
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.set_max_bytes_per_cmd(1);
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed[..2].to_vec(), vec!["SADD s a", "SADD s b"]);
+    assert_eq!(parsed.len(), 5 + 3 + 2 + 3 + 1);
+
+    // unlimited by default
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed[0], "SADD s a b c d e");
+    assert_eq!(parsed.len(), 5);
+}
+
+#[test]
+fn test_list_order() {
+    let values: Vec<Vec<u8>> = vec![b"first".to_vec(), b"second".to_vec(), b"third".to_vec()];
+
+    // a linked list, then the same list as a ziplist
+    let mut body = b"\xfe\x00".to_vec();
+    body.push(1);
+    body.extend(rdb_string(b"linked"));
+    body.extend(rdb_len(values.len()));
+    for value in &values {
+        body.extend(rdb_string(value));
+    }
+    let entries: Vec<_> = values.iter().map(|value| rdb_string(value)).collect();
+    body.extend(key_value(10, b"zipped", &ziplist(&entries)));
+    let buf = rdb("0006", &body);
+
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    let parsed: Vec<_> = dparser.read_to_cmd(&mut &buf[..])
+        .unwrap()
+        .into_iter()
+        .map(|cmd| cmd.into_data())
+        .collect();
+    assert_eq!(parsed.len(), 2);
+    for (cmd, key) in parsed.iter().zip(&[b"linked".to_vec(), b"zipped".to_vec()]) {
+        assert_eq!(cmd[0], b"RPUSH".to_vec());
+        assert_eq!(&cmd[1], key);
+        assert_eq!(cmd[2..].to_vec(), values);
+    }
+
+    // streamed in chunks, the order holds across commands
+    let mut dparser = libnewbee::DefaultRdbParser::default();
+    dparser.set_element_chunk(1);
+    let parsed = to_lines(dparser.read_to_cmd(&mut &buf[..]).unwrap());
+    assert_eq!(parsed,
+               vec!["RPUSH linked first",
+                    "RPUSH linked second",
+                    "RPUSH linked third",
+                    "RPUSH zipped first second third"]);
+}